@@ -0,0 +1,134 @@
+// Counts heap allocations and time spent intersecting rays with a scene.
+// Run with `cargo bench --bench hit_allocations`.
+//
+// `borrowed` is what the renderer does, the hit record borrows the material
+// of the sphere. `cloned` emulates the previous behaviour of boxing a copy
+// of the material for every candidate hit, for comparison.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use raytracer::{
+    interval::Interval, ray::Ray, Color, HitRecord, Hittable, HittableList, Material, Num, Point3,
+    Primitive, Sphere, Vec3,
+};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const RAYS: usize = 200_000;
+
+fn scene(rng: &mut Pcg32) -> Vec<Primitive> {
+    (0..64)
+        .map(|_| {
+            let center = Point3::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+            );
+            let material = Material::new_lambertian(Color::new(0.5, 0.5, 0.5));
+            Sphere::new(center, rng.gen_range(0.2..1.0), Box::new(material))
+        })
+        .collect()
+}
+
+fn rays(rng: &mut Pcg32) -> Vec<Ray> {
+    (0..RAYS)
+        .map(|_| {
+            let origin = 20. * Vec3::sample_unit_sphere((rng.gen(), rng.gen()));
+            let target = Point3::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+            );
+            Ray::new(origin, target - origin)
+        })
+        .collect()
+}
+
+// Closest hit, keeping every candidate record like `HittableList::hit`
+fn closest<'a>(
+    spheres: &'a [Primitive],
+    r: &Ray,
+    clone: bool,
+) -> Option<(Num, Option<Box<Material>>)> {
+    let mut hit: Option<HitRecord<'a>> = None;
+    let mut copy = None;
+    let mut closest_so_far = Ray::INFINITY;
+    for s in spheres {
+        if let Some(h) = s.hit(r, Interval::new(0.001, closest_so_far)) {
+            closest_so_far = h.t;
+            if clone {
+                copy = Some(Box::new(h.mat.clone()));
+            }
+            hit = Some(h);
+        }
+    }
+    hit.map(|h| (h.t, copy))
+}
+
+fn run(name: &str, spheres: &[Primitive], rays: &[Ray], clone: bool) {
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let mut hits = 0;
+    for r in rays {
+        if black_box(closest(spheres, r, clone)).is_some() {
+            hits += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    println!(
+        "{:<9} {:>8} hits {:>9} allocations ({:.2}/ray) {:>8.1} ns/ray",
+        name,
+        hits,
+        allocations,
+        allocations as f64 / rays.len() as f64,
+        elapsed.as_nanos() as f64 / rays.len() as f64
+    );
+}
+
+fn main() {
+    let mut rng = Pcg32::seed_from_u64(0);
+    let spheres = scene(&mut rng);
+    let rays = rays(&mut rng);
+
+    run("borrowed", &spheres, &rays, false);
+    run("cloned", &spheres, &rays, true);
+
+    // the whole scene through the public API
+    let world: HittableList = spheres.into_iter().collect();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    for r in rays.iter() {
+        black_box(
+            world
+                .hit(r, Interval::new(0.001, Ray::INFINITY))
+                .map(|h| h.t),
+        );
+    }
+    println!(
+        "HittableList::hit {} allocations for {} rays",
+        ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        rays.len()
+    );
+}
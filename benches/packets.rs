@@ -0,0 +1,119 @@
+// Compares tracing rays one at a time with tracing them in SIMD packets.
+// Run with `cargo bench --bench packets`, add
+// `RUSTFLAGS="-C target-cpu=native"` to use the AVX backend.
+//
+// Packets are filled with neighbouring primary rays, which is where they pay
+// off, the scattered rays of later bounces are far less coherent.
+
+use std::{hint::black_box, time::Instant};
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use raytracer::{
+    aabb::Aabb,
+    interval::Interval,
+    ray::Ray,
+    simd::{Floats, RayPacket, LANES},
+    Color, Hittable, HittableList, Material, Num, Point3, Sphere, Vec3,
+};
+
+const WIDTH: usize = 512;
+const HEIGHT: usize = 512;
+
+fn scene(rng: &mut Pcg32) -> HittableList {
+    let material = Material::new_lambertian(Color::new(0.5, 0.5, 0.5));
+    (0..256)
+        .map(|_| {
+            let center = Point3::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+            );
+            Sphere::new(center, rng.gen_range(0.1..0.5), material.clone())
+        })
+        .collect()
+}
+
+// Pinhole camera on the z axis looking at the origin, packets are runs of
+// `LANES` pixels in a row
+fn packets() -> Vec<[Ray; LANES]> {
+    let origin = Point3::new(0., 0., 15.);
+    let pixel = |i: usize, j: usize| {
+        let x = (i as Num + 0.5) / WIDTH as Num - 0.5;
+        let y = 0.5 - (j as Num + 0.5) / HEIGHT as Num;
+        Ray::new(origin, Vec3::new(x, y, -1.))
+    };
+    (0..HEIGHT)
+        .flat_map(|j| (0..WIDTH).step_by(LANES).map(move |i| (i, j)))
+        .map(|(i, j)| std::array::from_fn(|k| pixel(i + k, j)))
+        .collect()
+}
+
+fn report(name: &str, rays: usize, elapsed: std::time::Duration, check: Num) {
+    println!(
+        "{:<14} {:>8.1} ns/ray  (checksum {:.3})",
+        name,
+        elapsed.as_nanos() as f64 / rays as f64,
+        check
+    );
+}
+
+fn main() {
+    let mut rng = Pcg32::seed_from_u64(0);
+    let world = scene(&mut rng);
+    let packets = packets();
+    let rays = packets.len() * LANES;
+    let ray_t = Interval::new(0.001, Ray::INFINITY);
+    println!("{} rays, {} lanes per packet", rays, LANES);
+
+    // closest hit against the whole scene
+    let start = Instant::now();
+    let mut check = 0.;
+    for packet in &packets {
+        for r in packet {
+            check += black_box(world.hit(r, ray_t)).map_or(0., |h| h.t);
+        }
+    }
+    report("scalar hit", rays, start.elapsed(), check);
+
+    let start = Instant::now();
+    let mut check = 0.;
+    for packet in &packets {
+        for h in black_box(world.hit_packet(packet, ray_t)).iter().flatten() {
+            check += h.t;
+        }
+    }
+    report("packet hit", rays, start.elapsed(), check);
+
+    // box tests alone, as done for BVH nodes
+    let boxes: Vec<Aabb> = (0..256)
+        .map(|_| {
+            let a = Point3::new(
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+                rng.gen_range(-5.0..5.0),
+            );
+            Aabb::from_points(a, a + Vec3::new(1., 1., 1.))
+        })
+        .collect();
+
+    let start = Instant::now();
+    let mut hits = 0;
+    for packet in &packets {
+        for r in packet {
+            hits += boxes.iter().filter(|b| b.hit(r, ray_t)).count();
+        }
+    }
+    report("scalar aabb", rays, start.elapsed(), hits as Num);
+
+    let start = Instant::now();
+    let mut hits = 0;
+    let (t_min, t_max) = (Floats::splat(ray_t.min), Floats::splat(ray_t.max));
+    for packet in &packets {
+        let packet = RayPacket::new(packet);
+        for b in &boxes {
+            hits += b.hit_packet(&packet, t_min, t_max).bits().count_ones() as usize;
+        }
+    }
+    report("packet aabb", rays, start.elapsed(), hits as Num);
+}
@@ -0,0 +1,96 @@
+use crate::{
+    interval::Interval,
+    ray::Ray,
+    simd::{Floats, Mask, RayPacket},
+    vec3::Point3,
+    Num,
+};
+
+// Axis-aligned bounding box
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+impl Aabb {
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Aabb {
+        Aabb { x, y, z }
+    }
+
+    // Box spanned by two corner points, in any order
+    pub fn from_points(a: Point3, b: Point3) -> Aabb {
+        Aabb {
+            x: Interval::new(Num::min(a.x, b.x), Num::max(a.x, b.x)),
+            y: Interval::new(Num::min(a.y, b.y), Num::max(a.y, b.y)),
+            z: Interval::new(Num::min(a.z, b.z), Num::max(a.z, b.z)),
+        }
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb {
+            x: Interval::surrounding(&a.x, &b.x),
+            y: Interval::surrounding(&a.y, &b.y),
+            z: Interval::surrounding(&a.z, &b.z),
+        }
+    }
+
+    pub fn axis(&self, n: i32) -> &Interval {
+        match n {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    // Slab test
+    pub fn hit(&self, r: &Ray, mut ray_t: Interval) -> bool {
+        for a in 0..3 {
+            let inv_d = 1. / r.direction()[a];
+            let orig = r.origin()[a];
+            let axis = self.axis(a);
+
+            let mut t0 = (axis.min - orig) * inv_d;
+            let mut t1 = (axis.max - orig) * inv_d;
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            ray_t.min = Num::max(t0, ray_t.min);
+            ray_t.max = Num::min(t1, ray_t.max);
+            if ray_t.max <= ray_t.min {
+                return false;
+            }
+        }
+        true
+    }
+
+    // Slab test for a whole packet, lanes are hit if their `[t_min, t_max]`
+    // overlaps the box
+    pub fn hit_packet(&self, r: &RayPacket, mut t_min: Floats, mut t_max: Floats) -> Mask {
+        let slabs = [
+            (&self.x, r.origin.x, r.inv_dir.x),
+            (&self.y, r.origin.y, r.inv_dir.y),
+            (&self.z, r.origin.z, r.inv_dir.z),
+        ];
+        for (axis, orig, inv_d) in slabs {
+            let t0 = (Floats::splat(axis.min) - orig) * inv_d;
+            let t1 = (Floats::splat(axis.max) - orig) * inv_d;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+        t_max.gt(t_min)
+    }
+
+    pub const EMPTY: Aabb = Aabb {
+        x: Interval::EMPTY,
+        y: Interval::EMPTY,
+        z: Interval::EMPTY,
+    };
+    pub const UNIVERSE: Aabb = Aabb {
+        x: Interval::UNIVERSE,
+        y: Interval::UNIVERSE,
+        z: Interval::UNIVERSE,
+    };
+}
@@ -1,34 +1,123 @@
 use crate::{
-    color::{write_color, Color},
-    hittable::hittable_list::HittableList,
+    cancel::{CancelToken, RenderLimit},
+    checkpoint::{Checkpoint, Fingerprint},
+    color::Color,
+    consts,
+    framebuffer::{Framebuffer, Pixel},
+    hittable::{hittable_list::HittableList, HitRecord, Hittable},
     interval::Interval,
+    material::{Material, Shader},
     ray::Ray,
+    sampler::{Sampler, SamplerKind},
+    simd::{LANES, VECTORIZED},
+    stats::{self, ProgressObserver, RenderTracker},
     vec3::*,
     Num,
 };
 use crossbeam;
-use log::info;
-use rand::Rng;
-use std::{cmp::max, io::Write, sync::Arc};
 
-use log;
-use pretty_env_logger;
+use self::lens::{FocusedLens, MM};
+pub use self::{
+    animation::{CameraAnimation, Interpolation, Keyframe},
+    aperture::{Aperture, ApertureMask},
+    builder::CameraBuilder,
+    crop::Crop,
+    focus::FocusTarget,
+    lens::{LensElement, LensSystem},
+    projection::{FisheyeMapping, Projection},
+    stereo::{Stereo, StereoLayout},
+};
+
+pub mod animation;
+pub mod aperture;
+pub mod builder;
+pub mod crop;
+pub mod focus;
+pub mod lens;
+pub mod projection;
+pub mod stereo;
+use log::{info, warn};
+use std::{
+    cmp::{max, min},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 
 pub struct Camera {
-    pub aspect_ratio: Num,      // Ratio of image width over height
-    pub image_width: i32,       // Rendered image width in pixel count
-    pub samples_per_pixel: i32, // Count of random samples for each pixel
-    pub max_depth: i32,         // Maximum number of ray bounces into scene
+    pub aspect_ratio: Num,       // Ratio of image width over height
+    pub image_width: i32,        // Rendered image width in pixel count
+    pub resolution_percent: Num, // Scales the image size, e.g. 50 for quick previews
+    pub crop: Option<Crop>,      // Renders only part of the image
+    pub samples_per_pixel: i32,  // Count of random samples for each pixel
+    pub max_depth: i32,          // Maximum number of ray bounces into scene
+
+    // Paths that bounced `russian_roulette_depth` times are terminated with a
+    // probability based on their throughput, the survivors are weighted up so
+    // the image stays unbiased. Set it to `max_depth` or more to disable it.
+    pub russian_roulette_depth: i32,
+
+    // Adaptive sampling stops a pixel once the 95% confidence interval of its
+    // luminance is within `adaptive_threshold` of the mean, after at least
+    // `min_samples_per_pixel` and at most `samples_per_pixel` samples.
+    // A threshold of 0 disables it.
+    pub adaptive_threshold: Num,
+    pub min_samples_per_pixel: i32,
+
+    pub pass_samples: i32, // Samples per pixel added by each progressive pass
+    pub snapshot_interval: Option<Duration>, // Minimum time between progressive snapshots
+
+    // Rendering stops early when the budget runs out or the token is cancelled,
+    // the image then contains the samples taken up to that point
+    pub time_budget: Option<Duration>,
+    pub cancel_token: CancelToken,
 
-    pub vfov: Num,        // Vertical view angle (field of view)
+    pub progress: Option<Arc<dyn ProgressObserver>>, // Notified of progress and final statistics
+    pub progress_interval: Duration,                 // Minimum time between progress reports
+
+    pub checkpoint_path: Option<PathBuf>, // File the progressive render state is saved to
+    pub checkpoint_interval: Duration,    // Minimum time between checkpoints
+    pub resume: bool,                     // Continue from the checkpoint if there is one
+
+    pub projection: Projection,
+    pub vfov: Num, // Vertical view angle (field of view)
+
+    // Left and right eye views side by side or stacked. Perspective views use
+    // parallel eyes with shifted image planes, equirectangular panoramas are
+    // omni-directional stereo. Orthographic views are the same for both eyes.
+    pub stereo: Option<Stereo>,
+
+    // Traces through a real lens instead of the thin lens model, with the
+    // scene in meters. Replaces `vfov` and `defocus_angle`, the lens is
+    // focused at `focus_dist`.
+    pub lens: Option<LensSystem>,
     pub lookfrom: Point3, // Point camera is looking from
     pub lookat: Point3,   // Point camera is looking at
     pub vup: Vec3,        // Camera-relative "up" direction
 
     pub defocus_angle: Num,
     pub focus_dist: Num,
+    pub aperture: Aperture, // Shape of the lens opening and of out of focus highlights
+
+    // Moves the image plane sideways by a fraction of its width and height
+    // (right and up) without turning the camera, keeping vertical lines
+    // parallel when looking straight ahead.
+    pub lens_shift: (Num, Num),
+    // Tilts the plane in focus by angles about the horizontal and vertical
+    // camera axes. Positive angles move the focus further away towards the
+    // top and the right of the image.
+    pub lens_tilt: (Num, Num),
+
+    pub sampler: SamplerKind, // Sample pattern used for pixel, lens and bounce samples
+    pub seed: u64,            // Same scene and seed always give the same image
+    pub threads: usize,       // Worker thread count, 0 uses every available core
 
-    image_height: i32,   // Rendered image heihgt
+    render_width: i32, // Image width after applying `resolution_percent`
+    image_height: i32, // Rendered image heihgt
+    crop_rect: (i32, i32, i32, i32), // Rendered pixels as (x, y, width, height)
+    view_width: i32,   // Size of the view of one eye, the whole image without stereo
+    view_height: i32,
     center: Point3,      // Camera center
     pixel00_loc: Point3, // Location of pixel 0,0
     pixel_delta_u: Vec3, // Offset to pixel to the right
@@ -39,23 +128,58 @@ pub struct Camera {
 
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+
+    focus_normal: Vec3, // Normal of the tilted focus plane
+
+    focused_lens: Option<FocusedLens>,
 }
 
-impl Camera {
-    pub fn default() -> Camera {
+impl Default for Camera {
+    fn default() -> Camera {
         Camera {
             aspect_ratio: 1.0,
             image_width: 100,
+            resolution_percent: 100.,
+            crop: None,
             samples_per_pixel: 10,
+            render_width: 100,
             image_height: 100,
+            crop_rect: (0, 0, 100, 100),
+            view_width: 100,
+            view_height: 100,
             max_depth: 10,
+            russian_roulette_depth: 5,
+            adaptive_threshold: 0.,
+            min_samples_per_pixel: 16,
+
+            pass_samples: 1,
+            snapshot_interval: None,
+            time_budget: None,
+            cancel_token: CancelToken::new(),
+
+            progress: None,
+            progress_interval: Duration::from_secs(1),
+
+            checkpoint_path: None,
+            checkpoint_interval: Duration::from_secs(60),
+            resume: false,
 
-            vfov: std::f64::consts::PI / 2.,
+            projection: Projection::Perspective,
+            vfov: consts::PI / 2.,
+            stereo: None,
+            lens: None,
             lookfrom: Vec3::new(0., 0., -1.),
             lookat: Vec3::ZERO,
             vup: Vec3::new(0., 1., 0.),
             defocus_angle: 0.,
             focus_dist: 10.,
+            aperture: Aperture::Circle,
+            lens_shift: (0., 0.),
+            lens_tilt: (0., 0.),
+
+            sampler: SamplerKind::Independent,
+            seed: 0,
+            threads: 0,
 
             center: Vec3::ZERO,
             pixel00_loc: Vec3::ZERO,
@@ -67,104 +191,643 @@ impl Camera {
 
             defocus_disk_u: Vec3::ZERO,
             defocus_disk_v: Vec3::ZERO,
+
+            focus_normal: Vec3::ZERO,
+
+            focused_lens: None,
+        }
+    }
+}
+
+// State shared by the worker threads of a single render call
+struct RenderContext {
+    limit: RenderLimit,
+    tracker: RenderTracker,
+}
+
+impl Camera {
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::new()
+    }
+
+    // Render the whole image with `samples_per_pixel` samples per pixel,
+    // or as many as the time budget allows. With a `crop` only that region
+    // is rendered and returned.
+    pub fn render(&self, world: Arc<HittableList>) -> Framebuffer {
+        let ctx = self.render_context();
+        let mut image = self.new_framebuffer();
+        self.render_pass(&mut image, 0, self.samples_per_pixel, world, &ctx);
+        self.finish(&ctx);
+        image
+    }
+
+    // Render the whole image in passes of `pass_samples` samples per pixel
+    // until `samples_per_pixel` or the `time_budget` is reached.
+    // `snapshot` receives the intermediate image after every pass, or at most
+    // once every `snapshot_interval` when it is set.
+    // With a `checkpoint_path` the accumulated image is saved every
+    // `checkpoint_interval` and when rendering stops, and `resume` continues
+    // from that checkpoint up to `samples_per_pixel`.
+    pub fn render_progressive<F: FnMut(&Framebuffer)>(
+        &self,
+        world: Arc<HittableList>,
+        mut snapshot: F,
+    ) -> Framebuffer {
+        let ctx = self.render_context();
+        let mut last_snapshot = Instant::now();
+        let mut last_checkpoint = Instant::now();
+        // samples per pixel of the last pass that was not interrupted
+        let fingerprint = self.fingerprint(&world);
+        let (mut image, mut completed) = match self.load_checkpoint(fingerprint) {
+            Some(checkpoint) => (checkpoint.image, checkpoint.samples_per_pixel),
+            None => (self.new_framebuffer(), 0),
+        };
+        while completed < self.samples_per_pixel {
+            let target = min(
+                completed + max(1, self.pass_samples),
+                self.samples_per_pixel,
+            );
+            self.render_pass(&mut image, completed, target, world.clone(), &ctx);
+
+            let stopped = ctx.limit.reached();
+            if stopped {
+                info!(
+                    "Rendering stopped during pass to {} samples per pixel",
+                    target
+                );
+            } else {
+                completed = target;
+                info!("Finished pass with {} samples per pixel", target);
+            }
+
+            let finished = stopped || completed >= self.samples_per_pixel;
+            if finished || last_checkpoint.elapsed() >= self.checkpoint_interval {
+                image = ctx.tracker.time(
+                    |s| &mut s.checkpoint_time,
+                    || self.save_checkpoint(image, completed, fingerprint),
+                );
+                last_checkpoint = Instant::now();
+            }
+            if !finished {
+                let due = self
+                    .snapshot_interval
+                    .is_none_or(|interval| last_snapshot.elapsed() >= interval);
+                if due {
+                    ctx.tracker
+                        .time(|s| &mut s.snapshot_time, || snapshot(&image));
+                    last_snapshot = Instant::now();
+                }
+            }
+            if stopped {
+                break;
+            }
+        }
+        self.finish(&ctx);
+        image
+    }
+
+    // Renders the `crop` region into `image`, which has the size of the
+    // whole image, leaving the rest of it as it is
+    pub fn render_into(&self, world: Arc<HittableList>, image: &mut Framebuffer) {
+        let region = self.render(world);
+        image.paste(&region, self.crop_rect.0, self.crop_rect.1);
+    }
+
+    // Size of the whole image, after applying `resolution_percent`
+    pub fn image_size(&self) -> (i32, i32) {
+        (self.render_width, self.image_height)
+    }
+
+    // Rendered region as (x, y, width, height), the whole image without crop
+    pub fn crop_rect(&self) -> (i32, i32, i32, i32) {
+        self.crop_rect
+    }
+
+    fn new_framebuffer(&self) -> Framebuffer {
+        Framebuffer::new(self.crop_rect.2, self.crop_rect.3)
+    }
+
+    fn render_context(&self) -> RenderContext {
+        RenderContext {
+            limit: RenderLimit {
+                cancel: self.cancel_token.clone(),
+                deadline: self.time_budget.map(|budget| Instant::now() + budget),
+            },
+            tracker: RenderTracker::new(self.progress.clone(), self.progress_interval),
         }
     }
 
-    pub fn render<W: Write>(&self, f: &mut W, world: Arc<HittableList>) {
-        pretty_env_logger::init();
+    fn finish(&self, ctx: &RenderContext) {
+        let stats = ctx.tracker.finish();
+        info!("Render statistics:\n{}", stats);
+    }
 
-        write!(f, "P6\n{} {}\n255\n", self.image_width, self.image_height).unwrap();
+    // Hash of the scene and of the camera settings that change the image,
+    // other than the ones checkpoints store. The camera is covered by its
+    // initialized frame, so e.g. `lookfrom` and `vfov` are included.
+    fn fingerprint(&self, world: &HittableList) -> u64 {
+        let mut f = Fingerprint::new();
+        world.fingerprint(&mut f);
+        f.int(self.render_width as i64)
+            .int(self.image_height as i64)
+            .int(self.max_depth as i64)
+            .int(self.russian_roulette_depth as i64)
+            .num(self.adaptive_threshold)
+            .int(self.min_samples_per_pixel as i64);
+        for v in [
+            self.center,
+            self.pixel00_loc,
+            self.pixel_delta_u,
+            self.pixel_delta_v,
+            self.u,
+            self.v,
+            self.w,
+            self.defocus_disk_u,
+            self.defocus_disk_v,
+            self.focus_normal,
+        ] {
+            f.vec(&v);
+        }
+        f.debug(&self.projection)
+            .debug(&self.stereo)
+            .debug(&self.aperture)
+            .debug(&self.lens)
+            .num(self.focus_dist);
+        f.finish()
+    }
 
+    fn load_checkpoint(&self, fingerprint: u64) -> Option<Checkpoint> {
+        let path = self.checkpoint_path.as_ref().filter(|_| self.resume)?;
+        let checkpoint = match Checkpoint::load(path) {
+            Ok(checkpoint) => checkpoint,
+            Err(e) => {
+                warn!("Cannot resume from {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        if checkpoint.seed != self.seed
+            || checkpoint.sampler != self.sampler
+            || checkpoint.target_samples != self.samples_per_pixel
+            || checkpoint.crop_origin != (self.crop_rect.0, self.crop_rect.1)
+            || checkpoint.image.width != self.crop_rect.2
+            || checkpoint.image.height != self.crop_rect.3
+            || checkpoint.fingerprint != fingerprint
+        {
+            warn!(
+                "Checkpoint {} does not match the camera settings, starting over",
+                path.display()
+            );
+            return None;
+        }
+        info!(
+            "Resuming from {} at {} samples per pixel",
+            path.display(),
+            checkpoint.samples_per_pixel
+        );
+        Some(checkpoint)
+    }
+
+    // Takes the image by value to avoid copying it into the checkpoint
+    fn save_checkpoint(
+        &self,
+        image: Framebuffer,
+        samples_per_pixel: i32,
+        fingerprint: u64,
+    ) -> Framebuffer {
+        let Some(path) = &self.checkpoint_path else {
+            return image;
+        };
+        let checkpoint = Checkpoint {
+            seed: self.seed,
+            sampler: self.sampler,
+            target_samples: self.samples_per_pixel,
+            crop_origin: (self.crop_rect.0, self.crop_rect.1),
+            samples_per_pixel,
+            fingerprint,
+            image,
+        };
+        if let Err(e) = checkpoint.save(path) {
+            warn!("Cannot write checkpoint {}: {}", path.display(), e);
+        }
+        checkpoint.image
+    }
+
+    // Take samples in every pixel until it has `target_samples` of them,
+    // or until the limit is reached. `from_samples` is where the previous
+    // pass stopped, only used to report progress.
+    fn render_pass(
+        &self,
+        image: &mut Framebuffer,
+        from_samples: i32,
+        target_samples: i32,
+        world: Arc<HittableList>,
+        ctx: &RenderContext,
+    ) {
+        let width = image.width as usize;
+        let total = self.samples_per_pixel.max(1) as f64;
+        ctx.tracker.start_pass(
+            from_samples as f64 / total,
+            target_samples as f64 / total,
+            image.height,
+        );
+        let start = Instant::now();
+
+        // Rows are handed out to the workers on demand, every pixel sample
+        // has its own random stream so the order does not affect the result
+        let rows = Mutex::new(image.pixels.chunks_mut(width).enumerate());
         crossbeam::scope(|s| {
-            for j in 0..self.image_height {
-                let mut threads = Vec::with_capacity(self.image_width as usize);
-                for i in 0..self.image_width {
-                    info!("Processing pixel [{},{}]", j, i);
-
-                    let world = world.clone();
-                    let jh = s.spawn(move |_| {
-                        let mut pixel_color = Color::ZERO;
-                        // multiple samples per pixel
-                        for _sample in 0..self.samples_per_pixel {
-                            let r = self.get_ray(i, j);
-                            pixel_color += self.ray_color(&r, self.max_depth, world.clone());
-                        }
-                        pixel_color
+            for _ in 0..self.thread_count() {
+                let world = &*world;
+                let rows = &rows;
+                s.spawn(move |_| {
+                    // one sampler per packet lane, restarted for every sample
+                    let mut samplers: [Box<dyn Sampler>; LANES] = std::array::from_fn(|_| {
+                        self.sampler.create(self.samples_per_pixel, self.seed)
                     });
-                    threads.push(jh);
-                }
-                for th in threads {
-                    write_color(f, &th.join().unwrap(), self.samples_per_pixel);
-                }
+                    loop {
+                        if ctx.limit.reached() {
+                            break;
+                        }
+                        let Some((j, row)) = rows.lock().unwrap().next() else {
+                            break;
+                        };
+                        // the image may be a crop of the full frame
+                        let (x, y, _, _) = self.crop_rect;
+                        for (i, pixel) in row.iter_mut().enumerate() {
+                            self.sample_pixel(
+                                x + i as i32,
+                                y + j as i32,
+                                pixel,
+                                target_samples,
+                                world,
+                                &mut samplers,
+                                &ctx.limit,
+                            );
+                        }
+                        ctx.tracker.row_done();
+                    }
+                });
             }
         })
         .unwrap();
-        // for j in 0..self.image_height {
-        //     for i in 0..self.image_width {
-        //         info!("Processing pixel [{},{}]", j, i);
-
-        //         let mut pixel_color = Color::ZERO;
-        //         // multiple samples per pixel
-        //         for _sample in 0..self.samples_per_pixel {
-        //             let r = self.get_ray(i, j);
-        //             pixel_color += self.ray_color(&r, self.max_depth, world)
-        //         }
-        //         write_color(f, &pixel_color, self.samples_per_pixel);
-        //     }
-        // }
-    }
-
-    fn ray_color(&self, r: &Ray, depth: i32, world: Arc<HittableList>) -> Color {
-        if depth <= 0 {
-            return Color::ZERO;
-        }
-        if let Some(rec) = world.hit(r, Interval::new(0.001, Ray::INFINITY)) {
+        ctx.tracker.stats.lock().unwrap().render_time += start.elapsed();
+    }
+
+    // Samples are taken `LANES` at a time, tracing their camera rays as one
+    // packet with AVX. Every sample still has its own sampler stream, so the
+    // result is the same as taking them one by one.
+    #[allow(clippy::too_many_arguments)]
+    fn sample_pixel(
+        &self,
+        i: i32,
+        j: i32,
+        pixel: &mut Pixel,
+        target_samples: i32,
+        world: &HittableList,
+        samplers: &mut [Box<dyn Sampler>; LANES],
+        limit: &RenderLimit,
+    ) {
+        // at least two samples to estimate the variance, unless the budget is lower
+        let min_samples = (self.adaptive_threshold > 0.).then(|| {
+            self.min_samples_per_pixel
+                .max(2)
+                .min(self.samples_per_pixel)
+        });
+
+        // multiple samples per pixel
+        while pixel.samples < target_samples && !pixel.converged && !limit.reached() {
+            let first = pixel.samples;
+            let count = min(LANES as i32, target_samples - first) as usize;
+            let mut rays = [None; LANES];
+            for (lane, sampler) in samplers.iter_mut().enumerate().take(count) {
+                sampler.start_pixel_sample(i, j, first + lane as i32);
+                rays[lane] = self.get_ray(i, j, &mut **sampler);
+            }
+            let mut packet_hits = Self::camera_ray_hits(&rays, world);
+
+            for lane in 0..count {
+                if lane > 0 && (pixel.converged || limit.reached()) {
+                    break;
+                }
+                let color = match rays[lane] {
+                    Some((r, weight)) => {
+                        // lanes of the packet are only counted once they are used
+                        let hit = match &mut packet_hits {
+                            Some(hits) => {
+                                world.record_packet_lane();
+                                hits[lane].take()
+                            }
+                            None => world.hit(&r, Interval::new(0.001, Ray::INFINITY)),
+                        };
+                        weight * self.ray_color(&r, hit, world, &mut *samplers[lane])
+                    }
+                    None => Color::ZERO,
+                };
+                pixel.add_sample(&color);
+                stats::record_sample();
+
+                if min_samples.is_some_and(|min| pixel.samples >= min)
+                    && pixel.relative_error() <= self.adaptive_threshold
+                {
+                    pixel.converged = true;
+                }
+            }
+        }
+    }
+
+    // Closest hits of a batch of camera rays traced as a packet, None unless
+    // every lane has a ray. The other batches are traced one ray at a time.
+    // Packets are only used with AVX, which has to be enabled at compile time
+    // with `-C target-cpu=native` or `-C target-feature=+avx`, without it
+    // they are slower than single rays.
+    fn camera_ray_hits<'w>(
+        rays: &[Option<(Ray, Num)>; LANES],
+        world: &'w HittableList,
+    ) -> Option<[Option<HitRecord<'w>>; LANES]> {
+        let ray_t = Interval::new(0.001, Ray::INFINITY);
+        if VECTORIZED && rays.iter().all(Option::is_some) {
+            return Some(world.hit_packet(&rays.map(|r| r.unwrap().0), ray_t));
+        }
+        None
+    }
+
+    fn thread_count(&self) -> usize {
+        if self.threads > 0 {
+            return self.threads;
+        }
+        thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
+    // Radiance along `r`, whose closest hit `first_hit` is already known
+    fn ray_color<'w>(
+        &self,
+        r: &Ray,
+        first_hit: Option<HitRecord<'w>>,
+        world: &'w HittableList,
+        sampler: &mut dyn Sampler,
+    ) -> Color {
+        let mut radiance = Color::ZERO;
+        let mut throughput = Color::new(1., 1., 1.);
+        let mut ray = *r;
+        let mut first_hit = Some(first_hit);
+
+        for depth in 0..self.max_depth {
+            let hit = match first_hit.take() {
+                Some(hit) => hit,
+                None => world.hit(&ray, Interval::new(0.001, Ray::INFINITY)),
+            };
+            let Some(rec) = hit else {
+                let normal = normalize(ray.direction());
+                let a = 0.5 * (normal.y + 1.0);
+                let sky = (1. - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0);
+                radiance += throughput * &sky;
+                break;
+            };
             // let direction = Vec3::random_on_hemisphere(&rec.normal);
             // Lambertial distribution
             // let direction = rec.normal + Vec3::random_unit_sphere();
-            if let Some((attenuation, scattered)) = rec.mat.scatter(r, &rec) {
-                return attenuation * &self.ray_color(&scattered, depth - 1, world);
+            radiance += throughput * &rec.mat.emitted(&ray, &rec);
+            let Some((attenuation, scattered)) = rec.mat.scatter(&ray, &rec, sampler) else {
+                break;
+            };
+            throughput = throughput * &attenuation;
+            ray = scattered;
+
+            if let Material::Subsurface { .. } = rec.mat {
+                // refracted through the surface, continue inside the medium
+                if rec.front_face && dot(ray.direction(), &rec.normal) < 0. {
+                    let Some((weight, exit)) = self.subsurface_walk(ray, rec.mat, world, sampler)
+                    else {
+                        break;
+                    };
+                    throughput = throughput * &weight;
+                    ray = exit;
+                }
+            }
+
+            // Russian roulette, keeping paths in proportion to their throughput
+            if depth + 1 >= self.russian_roulette_depth {
+                let p = throughput.x.max(throughput.y).max(throughput.z).min(1.);
+                if sampler.get_1d() >= p {
+                    break;
+                }
+                throughput /= p;
             }
-            return Color::ZERO;
-            // return 0.5 * &self.ray_color(&Ray::new(rec.p, direction), depth - 1, world);
-            // return 0.5 * (rec.normal + Color::new(1., 1., 1.));
         }
+        radiance
+    }
+
+    // Random walk through a scattering medium, starting just below its surface.
+    // Distances are sampled from one randomly chosen channel, the path is
+    // weighted by the average over the channels of the pdf of the whole walk
+    // (spectral MIS), scattering is isotropic. Returns the path weight and the
+    // ray leaving the medium, None when the path is absorbed or the walk hits
+    // another object before leaving.
+    fn subsurface_walk(
+        &self,
+        entry: Ray,
+        medium: &Material,
+        world: &HittableList,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        const MAX_STEPS: i32 = 256;
+        let Material::Subsurface { albedo, mfp, .. } = *medium else {
+            return None;
+        };
+        let sigma_t = Color::new(1. / mfp.x, 1. / mfp.y, 1. / mfp.z);
+        let sigma_s = albedo * &sigma_t;
+        let transmittance = |t: Num| {
+            Color::new(
+                (-sigma_t.x * t).exp(),
+                (-sigma_t.y * t).exp(),
+                (-sigma_t.z * t).exp(),
+            )
+        };
+
+        // path contribution and the pdf of the path had each channel been
+        // sampled, both divided by the average pdf so far to stay in range
+        let mut contribution = Color::new(1., 1., 1.);
+        let mut pdf = Color::new(1., 1., 1.);
+        let mut extend = |f: Color, p: Color| {
+            contribution = contribution * &f;
+            pdf = pdf * &p;
+            let average = (pdf.x + pdf.y + pdf.z) / 3.;
+            contribution /= average;
+            pdf /= average;
+        };
+        // the channel sampling distances for the whole walk
+        let channel = Num::min(sampler.get_1d() * 3., 2.) as i32;
+        let mut ray = Ray::new(*entry.origin(), normalize(entry.direction()));
+        for _step in 0..MAX_STEPS {
+            // the surface was not closed
+            let rec = world.hit(&ray, Interval::new(0.001, Ray::INFINITY))?;
+
+            let t = -(1. - sampler.get_1d()).ln() * mfp[channel];
+
+            if t < rec.t {
+                // scattering event inside the medium
+                let tr = transmittance(t);
+                extend(sigma_s * &tr, sigma_t * &tr);
+                ray = Ray::new(ray.at(t), Vec3::sample_unit_sphere(sampler.get_2d()));
+                continue;
+            }
+            if !std::ptr::eq(rec.mat, medium) {
+                // another object inside the medium
+                return None;
+            }
 
-        let normal = normalize(r.direction());
-        let a = 0.5 * (normal.y + 1.0);
-        return (1. - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0);
+            // reached the boundary
+            let tr = transmittance(rec.t);
+            extend(tr, tr);
+
+            let (attenuation, scattered) = rec.mat.scatter(&ray, &rec, sampler)?;
+            if dot(scattered.direction(), &rec.normal) < 0. {
+                // left the medium
+                let average = (pdf.x + pdf.y + pdf.z) / 3.;
+                return Some((contribution * &attenuation / average, scattered));
+            }
+            extend(attenuation, Color::new(1., 1., 1.));
+            // internal reflection, keep walking
+            ray = Ray::new(*scattered.origin(), normalize(scattered.direction()));
+        }
+        None
     }
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
+
+    // Ray for a sample of pixel (i, j) and the weight of its radiance, None
+    // for pixels the projection does not cover
+    fn get_ray(&self, i: i32, j: i32, sampler: &mut dyn Sampler) -> Option<(Ray, Num)> {
+        let (eye, i, j) = self.view_pixel(i, j)?;
+        // offset of the eye from the camera center along `u`
+        let (half_iod, convergence) = match &self.stereo {
+            Some(stereo) => (eye * stereo.interocular / 2., stereo.convergence),
+            None => (0., 1.),
+        };
+
+        if let Some(lens) = &self.focused_lens {
+            let (sx, sy) = sampler.get_2d();
+            let s = (i as Num + sx) / self.view_width as Num;
+            let t = (j as Num + sy) / self.view_height as Num;
+            let (o, d, weight) = lens.sample_ray(s, t, sampler.get_2d())?;
+            let origin =
+                self.center + half_iod * self.u + MM * (o.x * self.u + o.y * self.v - o.z * self.w);
+            let direction = d.x * self.u + d.y * self.v - d.z * self.w;
+            return Some((Ray::new(origin, direction), weight));
+        }
+
+        if self.projection.is_panoramic() {
+            let (sx, sy) = sampler.get_2d();
+            let s = (i as Num + sx) / self.view_width as Num;
+            let t = (j as Num + sy) / self.view_height as Num;
+            let aspect = self.view_width as Num / self.view_height as Num;
+            let [x, y, z] = self.projection.panoramic_direction(s, t, aspect)?;
+            let direction = x * self.u + y * self.v - z * self.w;
+            if half_iod == 0. {
+                return Some((Ray::new(self.center, direction), 1.));
+            }
+
+            // equirectangular eyes sit on a circle, offset to the right of
+            // the horizontal view direction, other panoramas use fixed eyes
+            let offset = match self.projection {
+                Projection::Equirectangular => {
+                    let right = Vec3::new(z, 0., -x);
+                    if right.near_zero() {
+                        Vec3::ZERO
+                    } else {
+                        half_iod * normalize(&right)
+                    }
+                }
+                _ => Vec3::new(half_iod, 0., 0.),
+            };
+            let offset = offset.x * self.u + offset.y * self.v - offset.z * self.w;
+            // both eyes look at the same point at the convergence distance
+            let direction = convergence * normalize(&direction) - offset;
+            return Some((Ray::new(self.center + offset, direction), 1.));
+        }
+
         let pixel_center =
             self.pixel00_loc + (i as Num * self.pixel_delta_u) + (j as Num * self.pixel_delta_v);
-        let pixel_sample = pixel_center + self.pixel_sample_square();
+        let pixel_sample = pixel_center + self.pixel_sample_square(sampler);
 
+        // orthographic rays start on the plane through the camera center,
+        // behind their pixel on the focus plane.
+        // Perspective eyes are moved sideways, their image planes shifted back
+        // so points at the convergence distance project to the same pixel.
+        let (lens_center, focus_point) = match self.projection {
+            Projection::Orthographic { .. } => {
+                (pixel_sample + self.focus_dist * self.w, pixel_sample)
+            }
+            _ => (
+                self.center + half_iod * self.u,
+                pixel_sample + ((1. - self.focus_dist / convergence) * half_iod) * self.u,
+            ),
+        };
+        // with a tilted focus plane, focus where the ray through the lens
+        // center meets it
+        let focus_point = if self.lens_tilt == (0., 0.) {
+            focus_point
+        } else {
+            let chief = focus_point - lens_center;
+            let plane_point = self.center - self.focus_dist * self.w;
+            let t = dot(&(plane_point - lens_center), &self.focus_normal)
+                / dot(&chief, &self.focus_normal);
+            lens_center + t * chief
+        };
         let ray_origin = if self.defocus_angle <= 0. {
-            self.center
+            lens_center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(lens_center, sampler)
         };
-        let ray_direction = pixel_sample - ray_origin;
+        let ray_direction = focus_point - ray_origin;
 
-        return Ray::new(ray_origin, ray_direction);
+        Some((Ray::new(ray_origin, ray_direction), 1.))
+    }
+    // Eye (-1 left, 1 right, 0 without stereo) and position within its view
+    // of an image pixel
+    fn view_pixel(&self, i: i32, j: i32) -> Option<(Num, i32, i32)> {
+        let Some(stereo) = &self.stereo else {
+            return Some((0., i, j));
+        };
+        let (eye, i, j) = match stereo.layout {
+            StereoLayout::SideBySide if i >= self.view_width => (1., i - self.view_width, j),
+            StereoLayout::TopBottom if j >= self.view_height => (1., i, j - self.view_height),
+            _ => (-1., i, j),
+        };
+        // odd image sizes leave a line that belongs to neither view
+        (i < self.view_width && j < self.view_height).then_some((eye, i, j))
     }
-    fn pixel_sample_square(&self) -> Vec3 {
-        let mut rng = rand::thread_rng();
 
-        let px = -0.5 + rng.gen::<Num>();
-        let py = -0.5 + rng.gen::<Num>();
+    fn pixel_sample_square(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (sx, sy) = sampler.get_2d();
+        let px = -0.5 + sx;
+        let py = -0.5 + sy;
         (px * self.pixel_delta_u) + (py * self.pixel_delta_v)
     }
 
     pub fn initialize(&mut self) -> &Self {
-        self.image_height = max(1, (self.image_width as Num / self.aspect_ratio) as i32);
+        let scale = self.resolution_percent / 100.;
+        self.render_width = max(1, (self.image_width as Num * scale).round() as i32);
+        self.image_height = max(1, (self.render_width as Num / self.aspect_ratio) as i32);
+        self.crop_rect = match &self.crop {
+            Some(crop) => crop.rect(self.render_width, self.image_height),
+            None => (0, 0, self.render_width, self.image_height),
+        };
+        (self.view_width, self.view_height) = match self.stereo.map(|s| s.layout) {
+            Some(StereoLayout::SideBySide) => (max(1, self.render_width / 2), self.image_height),
+            Some(StereoLayout::TopBottom) => (self.render_width, max(1, self.image_height / 2)),
+            None => (self.render_width, self.image_height),
+        };
 
         self.center = self.lookfrom;
 
         // Determine viewport dimensions
-        let h = (self.vfov / 2.).tan();
-        let viewport_height = 2.0 * h * self.focus_dist;
-        let viewport_width = viewport_height * (self.image_width as Num / self.image_height as Num);
+        let viewport_height = match self.projection {
+            Projection::Orthographic { height } => height,
+            _ => {
+                let h = (self.vfov / 2.).tan();
+                2.0 * h * self.focus_dist
+            }
+        };
+        let viewport_width = viewport_height * (self.view_width as Num / self.view_height as Num);
 
         // Calculate the u,v,w unit basis vectors for the camera coordicate frame
         self.w = normalize(&(self.lookfrom - self.lookat));
@@ -176,24 +839,377 @@ impl Camera {
         let viewport_v = viewport_height * -self.v;
 
         // horizontal and vertiacal delta vectors from pixel to pixel
-        self.pixel_delta_u = viewport_u / self.image_width as Num;
-        self.pixel_delta_v = viewport_v / self.image_height as Num;
+        self.pixel_delta_u = viewport_u / self.view_width as Num;
+        self.pixel_delta_v = viewport_v / self.view_height as Num;
 
         // location of the uper left pixel
         let viewport_upper_left =
-            self.center - (self.focus_dist * self.w) - viewport_u / 2. - viewport_v / 2.;
+            self.center - (self.focus_dist * self.w) - viewport_u / 2. - viewport_v / 2.
+                + self.lens_shift.0 * viewport_u
+                - self.lens_shift.1 * viewport_v;
+        self.focus_normal = normalize(
+            &(self.w + self.lens_tilt.0.tan() * self.v + self.lens_tilt.1.tan() * self.u),
+        );
         self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
+        let aspect = self.view_width as Num / self.view_height as Num;
+        self.focused_lens = self.lens.as_ref().and_then(|lens| {
+            let focused = FocusedLens::new(lens, self.focus_dist / MM, aspect);
+            if focused.is_none() {
+                warn!("No light passes through the lens, using the thin lens model");
+            }
+            focused
+        });
+
         // Calculate the camera defocus disk basis vectors
         let defocus_radius = self.focus_dist * (self.defocus_angle / 2.).tan();
         self.defocus_disk_u = self.u * defocus_radius;
         self.defocus_disk_v = self.v * defocus_radius;
 
-        return self;
+        self
+    }
+
+    fn defocus_disk_sample(&self, center: Point3, sampler: &mut dyn Sampler) -> Vec3 {
+        let p = self.aperture.sample(sampler.get_2d());
+        center + p.x * self.defocus_disk_u + p.y * self.defocus_disk_v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::sphere::Sphere;
+
+    pub(crate) fn test_scene() -> Arc<HittableList> {
+        let mut world = HittableList::new();
+        world.add(Sphere::new(
+            Point3::new(0., -100.5, -1.),
+            100.,
+            Box::new(Material::new_lambertian(Color::new(0.5, 0.5, 0.5))),
+        ));
+        world.add(Sphere::new(
+            Point3::new(0., 0., -1.),
+            0.5,
+            Box::new(Material::new_metal(Color::new(0.8, 0.6, 0.2), 0.3)),
+        ));
+        Arc::new(world)
+    }
+
+    pub(crate) fn test_camera() -> CameraBuilder {
+        Camera::builder()
+            .image_width(16)
+            .samples_per_pixel(4)
+            .lookfrom(Point3::new(0., 0., 1.))
+            .lookat(Point3::new(0., 0., -1.))
+            .focus_dist(2.)
+    }
+
+    #[test]
+    fn render_does_not_depend_on_thread_count() {
+        for kind in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let render = |threads| {
+                test_camera()
+                    .sampler(kind)
+                    .seed(5)
+                    .threads(threads)
+                    .build()
+                    .render(test_scene())
+                    .to_rgb()
+            };
+            assert!(render(1) == render(4), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn adaptive_sampling_stops_converged_pixels() {
+        // the sky alone barely varies within a pixel
+        let image = test_camera()
+            .samples_per_pixel(64)
+            .adaptive_threshold(0.05)
+            .min_samples_per_pixel(8)
+            .build()
+            .render(Arc::new(HittableList::new()));
+        assert!(image
+            .pixels
+            .iter()
+            .all(|p| p.samples >= 8 && p.samples < 64));
+
+        let image = test_camera()
+            .samples_per_pixel(1)
+            .adaptive_threshold(0.05)
+            .build()
+            .render(test_scene());
+        assert!(image.pixels.iter().all(|p| p.samples == 1));
+    }
+
+    #[test]
+    fn renders_with_one_sample_per_pixel() {
+        let cam = test_camera().samples_per_pixel(1).build();
+        let image = cam.render(test_scene());
+        assert!(image.pixels.iter().all(|p| p.samples == 1));
+        let image = cam.render_progressive(test_scene(), |_| {});
+        assert!(image.pixels.iter().all(|p| p.samples == 1));
     }
 
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = Vec3::random_unit_in_disk();
-        self.center + p.x * self.defocus_disk_u + p.y * self.defocus_disk_v
+    #[test]
+    fn progressive_render_reaches_the_sample_count() {
+        let mut snapshots = 0;
+        let image = test_camera()
+            .samples_per_pixel(7)
+            .pass_samples(3)
+            .build()
+            .render_progressive(test_scene(), |_| snapshots += 1);
+        assert_eq!(snapshots, 2);
+        assert!(image.pixels.iter().all(|p| p.samples == 7));
+    }
+
+    #[test]
+    fn resume_only_matching_checkpoints() {
+        let path = std::env::temp_dir().join(format!("resume-{}.ckpt", std::process::id()));
+        let corner = Crop::Pixels {
+            x: 0,
+            y: 0,
+            width: 4,
+            height: 4,
+        };
+        let cam =
+            |cam: CameraBuilder, crop| cam.crop(crop).checkpoint_path(&path).resume(true).build();
+        let first = cam(test_camera(), corner);
+        // a finished checkpoint marked with an impossible sample count
+        let mark = || {
+            let mut image = first.new_framebuffer();
+            image.pixels[0].samples = 1000;
+            first.save_checkpoint(
+                image,
+                first.samples_per_pixel,
+                first.fingerprint(&test_scene()),
+            );
+        };
+        let resumed_samples = |cam: &Camera, world| {
+            mark();
+            cam.render_progressive(world, |_| {}).pixels[0].samples
+        };
+        assert_eq!(resumed_samples(&first, test_scene()), 1000);
+
+        // same size, different region
+        let moved = Crop::Pixels {
+            x: 4,
+            y: 0,
+            width: 4,
+            height: 4,
+        };
+        let moved = cam(test_camera(), moved);
+        assert_eq!(resumed_samples(&moved, test_scene()), 4);
+
+        // same region of another view or another scene
+        let turned = cam(test_camera().lookfrom(Point3::new(0.1, 0., 1.)), corner);
+        assert_eq!(resumed_samples(&turned, test_scene()), 4);
+        let deeper = cam(test_camera().max_depth(3), corner);
+        assert_eq!(resumed_samples(&deeper, test_scene()), 4);
+        let empty = Arc::new(HittableList::new());
+        assert_eq!(resumed_samples(&first, empty), 4);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn white_subsurface_medium_does_not_add_energy() {
+        for mfp in [Color::new(0.1, 0.1, 0.1), Color::new(0.05, 0.1, 0.2)] {
+            let mut world = HittableList::new();
+            world.add(Sphere::new(
+                Point3::new(0., 0., -1.),
+                0.5,
+                Box::new(Material::new_subsurface(Color::new(1., 1., 1.), mfp, 1.3)),
+            ));
+            let image = test_camera()
+                .vfov(consts::PI / 6.)
+                .samples_per_pixel(64)
+                .max_depth(64)
+                .build()
+                .render(Arc::new(world));
+            // every view pixel lands on the sphere, under a sky no brighter
+            // than 1 in any channel
+            let mut total = Color::ZERO;
+            for p in &image.pixels {
+                total += p.color();
+            }
+            let mean = total / image.pixels.len() as Num;
+            for c in 0..3 {
+                assert!(mean[c] <= 1.05, "{} with mfp {}", mean, mfp);
+            }
+        }
+    }
+
+    #[test]
+    fn cancelled_render_keeps_partial_image() {
+        let cam = test_camera().build();
+        cam.cancel_token.cancel();
+        let image = cam.render(test_scene());
+        assert_eq!(image.pixels.len(), 16 * 16);
+        assert!(image.pixels.iter().all(|p| p.samples == 0));
+
+        let image = test_camera()
+            .time_budget(Duration::ZERO)
+            .build()
+            .render_progressive(test_scene(), |_| panic!("no pass finished"));
+        assert!(image.pixels.iter().all(|p| p.samples == 0));
+    }
+
+    #[test]
+    fn progress_observer_sees_the_whole_render() {
+        struct Recorder(Mutex<(Vec<f64>, Option<stats::RenderStats>)>);
+        impl ProgressObserver for Recorder {
+            fn on_progress(&self, progress: &stats::Progress) {
+                self.0.lock().unwrap().0.push(progress.fraction);
+            }
+            fn on_finish(&self, stats: &stats::RenderStats) {
+                self.0.lock().unwrap().1 = Some(stats.clone());
+            }
+        }
+
+        let recorder = Arc::new(Recorder(Mutex::new((Vec::new(), None))));
+        test_camera()
+            .progress(recorder.clone())
+            .progress_interval(Duration::ZERO)
+            .threads(1)
+            .build()
+            .render(test_scene());
+
+        let (fractions, stats) = &*recorder.0.lock().unwrap();
+        assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(fractions.last(), Some(&1.));
+        let stats = stats.as_ref().unwrap();
+        assert_eq!(stats.samples, 16 * 16 * 4);
+        assert!(stats.rays_cast >= stats.samples);
+        assert!(stats.average_path_depth() >= 1.);
+    }
+
+    #[test]
+    fn only_used_packet_lanes_are_counted() {
+        struct Finish(Mutex<Option<stats::RenderStats>>);
+        impl ProgressObserver for Finish {
+            fn on_progress(&self, _: &stats::Progress) {}
+            fn on_finish(&self, stats: &stats::RenderStats) {
+                *self.0.lock().unwrap() = Some(stats.clone());
+            }
+        }
+
+        // camera rays into the empty sky end their paths, and adaptive
+        // sampling stops pixels in the middle of a packet
+        let finish = Arc::new(Finish(Mutex::new(None)));
+        test_camera()
+            .samples_per_pixel(2 * LANES as i32 + 1)
+            .adaptive_threshold(0.05)
+            .min_samples_per_pixel(3)
+            .progress(finish.clone())
+            .build()
+            .render(Arc::new(HittableList::new()));
+        let stats = finish.0.lock().unwrap().clone().unwrap();
+        assert_eq!(stats.rays_cast, stats.samples);
+    }
+
+    #[test]
+    fn russian_roulette_keeps_the_mean() {
+        let mean = |rr_depth| {
+            let image = test_camera()
+                .image_width(8)
+                .samples_per_pixel(256)
+                .max_depth(10)
+                .russian_roulette_depth(rr_depth)
+                .build()
+                .render(test_scene());
+            let sum: Num = image.pixels.iter().map(|p| p.color().y).sum();
+            sum / image.pixels.len() as Num
+        };
+        let (full, roulette) = (mean(10), mean(1));
+        assert!(
+            (full - roulette).abs() < 0.01 * full,
+            "{} {}",
+            full,
+            roulette
+        );
+    }
+
+    // Samples the center of every pixel and of the lens
+    pub(crate) struct Centered;
+
+    impl Sampler for Centered {
+        fn start_pixel_sample(&mut self, _: i32, _: i32, _: i32) {}
+        fn get_1d(&mut self) -> Num {
+            0.5
+        }
+    }
+
+    // Camera ray of the first sample of pixel (i, j)
+    pub(crate) fn camera_ray(cam: &Camera, i: i32, j: i32) -> Option<(Ray, Num)> {
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        sampler.start_pixel_sample(i, j, 0);
+        cam.get_ray(i, j, &mut *sampler)
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let cam = test_camera()
+            .projection(Projection::Orthographic { height: 2. })
+            .build();
+        let rays: Vec<Ray> = [(0, 0), (15, 0), (8, 15)]
+            .iter()
+            .map(|&(i, j)| camera_ray(&cam, i, j).unwrap().0)
+            .collect();
+        for r in rays.iter() {
+            let d = normalize(r.direction());
+            assert!((d.z + 1.).abs() < 1e-6);
+        }
+        // origins span the view plane
+        let height = rays[0].origin().y - rays[2].origin().y;
+        assert!(height > 1.5 && height < 2.);
+        assert!(rays[1].origin().x - rays[0].origin().x > 1.5);
+    }
+
+    #[test]
+    fn lens_shift_moves_the_view_without_turning() {
+        let straight = test_camera().build();
+        let shifted = test_camera().lens_shift((0., 0.25)).build();
+        let (a, _) = straight.get_ray(8, 8, &mut Centered).unwrap();
+        let (b, _) = shifted.get_ray(8, 8, &mut Centered).unwrap();
+        // a quarter of the 4 units tall view plane at the focus distance
+        assert!((b.direction().y - a.direction().y - 1.).abs() < 1e-6);
+        assert!((b.direction().z - a.direction().z).abs() < 1e-6);
+        assert!(dot(&straight.w, &shifted.w) > 1. - 1e-6);
+    }
+
+    #[test]
+    fn tilted_focus_plane_recedes_towards_the_top() {
+        let cam = test_camera()
+            .defocus_angle(0.1)
+            .lens_tilt((0.3, 0.))
+            .build();
+        // camera rays end on the focus plane
+        let focus = |j| {
+            let (r, _) = cam.get_ray(8, j, &mut Centered).unwrap();
+            *r.origin() + *r.direction()
+        };
+        let (top, bottom) = (focus(0), focus(15));
+        let plane_point = cam.center - cam.focus_dist * cam.w;
+        for p in [top, bottom] {
+            assert!(dot(&(p - plane_point), &cam.focus_normal).abs() < 1e-6);
+        }
+        assert!(top.z < bottom.z);
+    }
+
+    #[test]
+    fn seed_changes_the_image() {
+        let render = |seed| {
+            test_camera()
+                .seed(seed)
+                .build()
+                .render(test_scene())
+                .to_rgb()
+        };
+        assert!(render(1) != render(2));
     }
 }
@@ -0,0 +1,328 @@
+use std::sync::Arc;
+
+use super::Camera;
+use crate::{
+    encode,
+    framebuffer::Framebuffer,
+    hittable::hittable_list::HittableList,
+    vec3::{Point3, Vec3},
+    Num,
+};
+
+// Animated camera parameters at a point in time
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: Num,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vfov: Num,
+    pub focus_dist: Num,
+}
+
+impl Keyframe {
+    // The current parameters of `cam`
+    pub fn from_camera(time: Num, cam: &Camera) -> Keyframe {
+        Keyframe {
+            time,
+            lookfrom: cam.lookfrom,
+            lookat: cam.lookat,
+            vfov: cam.vfov,
+            focus_dist: cam.focus_dist,
+        }
+    }
+
+    fn place(&self, cam: &mut Camera) {
+        cam.lookfrom = self.lookfrom;
+        cam.lookat = self.lookat;
+        cam.vfov = self.vfov;
+        cam.focus_dist = self.focus_dist;
+        cam.initialize();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interpolation {
+    Linear,
+    // Catmull-Rom spline through the keyframes, smooth at every keyframe
+    Spline,
+}
+
+// Keyframed camera path. Before the first and after the last keyframe the
+// camera holds still, unless the animation is looping.
+#[derive(Clone)]
+pub struct CameraAnimation {
+    pub interpolation: Interpolation,
+    // Repeats from the first keyframe after the last one, which should be
+    // the same as the first. The spline runs smoothly across the seam.
+    pub looping: bool,
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraAnimation {
+    pub fn new(interpolation: Interpolation) -> CameraAnimation {
+        CameraAnimation {
+            interpolation,
+            looping: false,
+            keyframes: Vec::new(),
+        }
+    }
+
+    // Keyframes can be added in any order
+    pub fn add(&mut self, keyframe: Keyframe) {
+        let index = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(index, keyframe);
+    }
+
+    // Builder-style `add`
+    pub fn key(mut self, keyframe: Keyframe) -> Self {
+        self.add(keyframe);
+        self
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    // Time of the first and last keyframe
+    pub fn duration(&self) -> (Num, Num) {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => (first.time, last.time),
+            _ => (0., 0.),
+        }
+    }
+
+    // Interpolated parameters, None without keyframes
+    pub fn at(&self, time: Num) -> Option<Keyframe> {
+        let keys = &self.keyframes;
+        let last = keys.len().checked_sub(1)?;
+        let (start, end) = self.duration();
+        let looping = self.looping && last >= 2 && end > start;
+        let time = if looping {
+            start + (time - start).rem_euclid(end - start)
+        } else {
+            time
+        };
+        // segment [k1, k2] containing the time
+        let k2 = keys
+            .partition_point(|k| k.time <= time)
+            .clamp(1, last.max(1));
+        let k1 = k2 - 1;
+        if last == 0 || time <= keys[0].time {
+            return Some(Keyframe { time, ..keys[0] });
+        }
+        if time >= keys[last].time {
+            return Some(Keyframe { time, ..keys[last] });
+        }
+
+        let span = keys[k2].time - keys[k1].time;
+        let t = if span > 0. {
+            (time - keys[k1].time) / span
+        } else {
+            1.
+        };
+        // neighbours for the spline tangents, repeated at the ends or taken
+        // from across the seam of a loop, where the last key is the first
+        let (k0, k3) = if looping {
+            (
+                if k1 == 0 { last - 1 } else { k1 - 1 },
+                if k2 == last { 1 } else { k2 + 1 },
+            )
+        } else {
+            (k1.saturating_sub(1), (k2 + 1).min(last))
+        };
+        let [p0, p1, p2, p3] = [k0, k1, k2, k3].map(|k| &keys[k]);
+
+        let num = |f: fn(&Keyframe) -> Num| self.blend(t, f(p0), f(p1), f(p2), f(p3));
+        let vec = |f: fn(&Keyframe) -> Vec3| {
+            let (a, b, c, d) = (f(p0), f(p1), f(p2), f(p3));
+            Vec3::new(
+                self.blend(t, a.x, b.x, c.x, d.x),
+                self.blend(t, a.y, b.y, c.y, d.y),
+                self.blend(t, a.z, b.z, c.z, d.z),
+            )
+        };
+        Some(Keyframe {
+            time,
+            lookfrom: vec(|k| k.lookfrom),
+            lookat: vec(|k| k.lookat),
+            vfov: num(|k| k.vfov),
+            focus_dist: num(|k| k.focus_dist),
+        })
+    }
+
+    // Value between p1 and p2 at `t` in [0,1]
+    fn blend(&self, t: Num, p0: Num, p1: Num, p2: Num, p3: Num) -> Num {
+        match self.interpolation {
+            Interpolation::Linear => p1 + (p2 - p1) * t,
+            Interpolation::Spline => {
+                let (t2, t3) = (t * t, t * t * t);
+                0.5 * (2. * p1
+                    + (p2 - p0) * t
+                    + (2. * p0 - 5. * p1 + 4. * p2 - p3) * t2
+                    + (3. * p1 - p0 - 3. * p2 + p3) * t3)
+            }
+        }
+    }
+
+    // Moves `cam` to its place at `time`
+    pub fn apply(&self, cam: &mut Camera, time: Num) {
+        if let Some(k) = self.at(time) {
+            k.place(cam);
+        }
+    }
+}
+
+impl Camera {
+    // Renders `frames` frames spread evenly from the first to the last
+    // keyframe. Looping animations stop one frame short of the last keyframe,
+    // so the first frame is not repeated.
+    // Frames are rendered progressively, `on_frame` receives the snapshots
+    // and then the finished image of each frame with its number, and whether
+    // the image is the finished one. With a `checkpoint_path` every frame has
+    // its own checkpoint, numbered like `encode::frame_path`, and `resume`
+    // continues an interrupted sequence.
+    // All frames share the scene, the time budget applies to each frame and
+    // rendering stops after the frame the cancel token was triggered in.
+    // The camera is back at its own place afterwards.
+    pub fn render_sequence<F: FnMut(usize, &Framebuffer, bool)>(
+        &mut self,
+        world: Arc<HittableList>,
+        animation: &CameraAnimation,
+        frames: usize,
+        mut on_frame: F,
+    ) {
+        let (start, end) = animation.duration();
+        let steps = if animation.looping {
+            frames
+        } else {
+            frames.saturating_sub(1)
+        };
+        let checkpoint_path = self.checkpoint_path.clone();
+        let home = Keyframe::from_camera(start, self);
+        for frame in 0..frames {
+            let time = start + (end - start) * frame as Num / steps.max(1) as Num;
+            animation.apply(self, time);
+            self.checkpoint_path = checkpoint_path
+                .as_ref()
+                .map(|path| encode::frame_path(path, frame));
+            let image =
+                self.render_progressive(world.clone(), |snapshot| on_frame(frame, snapshot, false));
+            on_frame(frame, &image, true);
+            if self.cancel_token.is_cancelled() {
+                break;
+            }
+        }
+        self.checkpoint_path = checkpoint_path;
+        home.place(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::tests::{test_camera, test_scene};
+
+    fn key(time: Num, x: Num) -> Keyframe {
+        Keyframe {
+            time,
+            lookfrom: Point3::new(x, 1., 2. * x),
+            lookat: Point3::new(0., x, 0.),
+            vfov: 0.5 + x,
+            focus_dist: 1. + x * x,
+        }
+    }
+
+    fn same(a: &Keyframe, b: &Keyframe) -> bool {
+        let v = |v: Vec3| (v.x, v.y, v.z);
+        (a.time, v(a.lookfrom), v(a.lookat), a.vfov, a.focus_dist)
+            == (b.time, v(b.lookfrom), v(b.lookat), b.vfov, b.focus_dist)
+    }
+
+    #[test]
+    fn keyframes_are_hit_exactly() {
+        let keys = [key(2., 3.), key(0., 1.), key(0.5, -2.), key(3., 0.25)];
+        for interpolation in [Interpolation::Linear, Interpolation::Spline] {
+            let animation = keys
+                .iter()
+                .fold(CameraAnimation::new(interpolation), |a, &k| a.key(k));
+            for k in &keys {
+                assert!(
+                    same(&animation.at(k.time).unwrap(), k),
+                    "{:?}",
+                    interpolation
+                );
+            }
+            // holds still outside the keyframes
+            assert!(same(&animation.at(-1.).unwrap(), &key(-1., 1.)));
+            assert!(same(&animation.at(5.).unwrap(), &key(5., 0.25)));
+        }
+        assert!(CameraAnimation::new(Interpolation::Linear).at(0.).is_none());
+    }
+
+    #[test]
+    fn looping_animation_wraps_around() {
+        let mut animation = CameraAnimation::new(Interpolation::Spline)
+            .key(key(0., 0.))
+            .key(key(1., 1.))
+            .key(key(2., 0.));
+        animation.looping = true;
+        let (a, b) = (animation.at(0.25).unwrap(), animation.at(2.25).unwrap());
+        assert!(same(&a, &Keyframe { time: 0.25, ..b }));
+        let before = animation.at(-0.25).unwrap();
+        assert!(same(
+            &before,
+            &Keyframe {
+                time: 1.75,
+                ..animation.at(1.75).unwrap()
+            }
+        ));
+    }
+
+    #[test]
+    fn looping_sequence_does_not_repeat_the_first_frame() {
+        let path = std::env::temp_dir().join(format!("sequence-{}.ckpt", std::process::id()));
+        let mut cam = test_camera()
+            .samples_per_pixel(2)
+            .pass_samples(1)
+            .checkpoint_path(&path)
+            .build();
+        let around = |x: Num, z: Num| Keyframe {
+            lookfrom: Point3::new(x, 0., z - 1.),
+            ..Keyframe::from_camera(0., &cam)
+        };
+        let mut animation = CameraAnimation::new(Interpolation::Linear)
+            .key(Keyframe {
+                time: 0.,
+                ..around(0., 2.)
+            })
+            .key(Keyframe {
+                time: 1.,
+                ..around(0., -2.)
+            })
+            .key(Keyframe {
+                time: 2.,
+                ..around(0., 2.)
+            });
+        animation.looping = true;
+
+        let mut frames = Vec::new();
+        let mut snapshots = Vec::new();
+        cam.render_sequence(test_scene(), &animation, 2, |n, image, finished| {
+            if finished {
+                frames.push((n, image.to_rgb()));
+            } else {
+                snapshots.push(n);
+            }
+        });
+        let numbers: Vec<_> = frames.iter().map(|(n, _)| *n).collect();
+        assert_eq!(numbers, [0, 1]);
+        assert_eq!(snapshots, [0, 1]);
+        // the second frame looks from behind instead of wrapping back
+        assert!(frames[0].1 != frames[1].1);
+        assert_eq!(cam.checkpoint_path.as_ref(), Some(&path));
+        assert_eq!(cam.lookfrom.z, 1.);
+        for n in 0..2 {
+            std::fs::remove_file(encode::frame_path(&path, n)).unwrap();
+        }
+    }
+}
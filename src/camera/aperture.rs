@@ -0,0 +1,251 @@
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{consts, vec3::Vec3, Num};
+
+// Shape of the lens opening, which is also the shape of out of focus
+// highlights (bokeh)
+#[derive(Clone, Debug)]
+pub enum Aperture {
+    Circle,
+    // Regular polygon formed by `blades` straight blades, `rotation` in
+    // radians turns it counterclockwise
+    Polygon { blades: u32, rotation: Num },
+    // Grayscale image covering the square around the unit disk, brighter
+    // pixels let more light through
+    Mask(Arc<ApertureMask>),
+}
+
+impl Aperture {
+    // Point on the aperture, within [-1,1]², from two uniform samples in
+    // [0,1). Points are distributed like the light passing through it.
+    pub fn sample(&self, u: (Num, Num)) -> Vec3 {
+        match self {
+            Self::Polygon { blades, rotation } if *blades >= 3 => {
+                // pick one of the triangles between the center and an edge,
+                // reusing the first sample for the position inside it
+                let n = *blades as Num;
+                let k = Num::min((u.0 * n).floor(), n - 1.);
+                let u0 = u.0 * n - k;
+                let angle = |k: Num| rotation + 2. * consts::PI * k / n;
+                let (a, b) = (angle(k), angle(k + 1.));
+
+                let su = u0.sqrt();
+                let (wa, wb) = (su * (1. - u.1), su * u.1);
+                Vec3::new(wa * a.cos() + wb * b.cos(), wa * a.sin() + wb * b.sin(), 0.)
+            }
+            Self::Mask(mask) => mask.sample(u),
+            _ => Vec3::sample_unit_in_disk(u),
+        }
+    }
+}
+
+// Transmission image sampled through its piecewise constant distribution
+#[derive(Debug)]
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    row_cdf: Vec<Num>,   // Cumulative row sums, height + 1 entries
+    pixel_cdf: Vec<Num>, // Cumulative sums within each row, width + 1 entries per row
+}
+
+impl ApertureMask {
+    // Transmission values in [0,1] row by row from the top
+    pub fn new(width: usize, height: usize, values: &[Num]) -> io::Result<ApertureMask> {
+        if width == 0 || height == 0 || values.len() != width * height {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "mask size does not match its values",
+            ));
+        }
+        let mut row_cdf = vec![0.; height + 1];
+        let mut pixel_cdf = vec![0.; (width + 1) * height];
+        for (j, row) in values.chunks(width).enumerate() {
+            let cdf = &mut pixel_cdf[j * (width + 1)..(j + 1) * (width + 1)];
+            for (i, v) in row.iter().enumerate() {
+                cdf[i + 1] = cdf[i] + v.max(0.);
+            }
+            row_cdf[j + 1] = row_cdf[j] + cdf[width];
+        }
+        if row_cdf[height] <= 0. {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                "mask does not let any light through",
+            ));
+        }
+        Ok(ApertureMask {
+            width,
+            height,
+            row_cdf,
+            pixel_cdf,
+        })
+    }
+
+    // Binary (P5) or plain (P2) PGM image
+    pub fn load(path: &Path) -> io::Result<ApertureMask> {
+        let (width, height, values) = read_pgm(&fs::read(path)?)?;
+        ApertureMask::new(width, height, &values)
+    }
+
+    fn sample(&self, u: (Num, Num)) -> Vec3 {
+        let (j, dv) = sample_cdf(&self.row_cdf, u.1);
+        let cdf = &self.pixel_cdf[j * (self.width + 1)..(j + 1) * (self.width + 1)];
+        let (i, du) = sample_cdf(cdf, u.0);
+        Vec3::new(
+            2. * (i as Num + du) / self.width as Num - 1.,
+            1. - 2. * (j as Num + dv) / self.height as Num,
+            0.,
+        )
+    }
+}
+
+// Bucket of `cdf` that `u` falls in and the position within it
+fn sample_cdf(cdf: &[Num], u: Num) -> (usize, Num) {
+    let total = cdf[cdf.len() - 1];
+    let target = u * total;
+    // last entry not above the target, skipping empty buckets
+    let k = cdf
+        .partition_point(|&c| c <= target)
+        .clamp(1, cdf.len() - 1)
+        - 1;
+    let width = cdf[k + 1] - cdf[k];
+    let offset = if width > 0. {
+        (target - cdf[k]) / width
+    } else {
+        0.5
+    };
+    (k, offset.clamp(0., 1.))
+}
+
+fn read_pgm(data: &[u8]) -> io::Result<(usize, usize, Vec<Num>)> {
+    let invalid = |msg: &str| io::Error::new(ErrorKind::InvalidData, format!("PGM: {}", msg));
+
+    // header fields are separated by whitespace, `#` comments run to the
+    // end of the line
+    let mut pos = 0;
+    let mut field = || -> io::Result<&[u8]> {
+        loop {
+            match data.get(pos) {
+                Some(b'#') => {
+                    while data.get(pos).is_some_and(|&c| c != b'\n') {
+                        pos += 1;
+                    }
+                }
+                Some(c) if c.is_ascii_whitespace() => pos += 1,
+                Some(_) => break,
+                None => return Err(invalid("truncated")),
+            }
+        }
+        let start = pos;
+        while data.get(pos).is_some_and(|c| !c.is_ascii_whitespace()) {
+            pos += 1;
+        }
+        Ok(&data[start..pos])
+    };
+    let number = |f: &[u8]| -> io::Result<usize> {
+        std::str::from_utf8(f)
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid("bad number"))
+    };
+
+    let magic = field()?.to_vec();
+    let width = number(field()?)?;
+    let height = number(field()?)?;
+    let max = number(field()?)?;
+    if max == 0 || max > 65535 {
+        return Err(invalid("bad maximum value"));
+    }
+    // every value takes at least one byte, which bounds the sizes by the
+    // file size before anything is allocated
+    let count = width
+        .checked_mul(height)
+        .filter(|&n| n <= data.len())
+        .ok_or_else(|| invalid("truncated"))?;
+    let samples: Vec<usize> = match magic.as_slice() {
+        b"P2" => (0..count)
+            .map(|_| field().and_then(number))
+            .collect::<io::Result<_>>()?,
+        b"P5" => {
+            // a single whitespace character precedes the raster
+            let raster = data.get(pos + 1..).unwrap_or_default();
+            let bytes = if max > 255 { 2 } else { 1 };
+            if count.checked_mul(bytes).is_none_or(|n| raster.len() < n) {
+                return Err(invalid("truncated"));
+            }
+            raster
+                .chunks(bytes)
+                .take(count)
+                .map(|b| b.iter().fold(0, |v, &c| v << 8 | c as usize))
+                .collect()
+        }
+        _ => return Err(invalid("not a PGM image")),
+    };
+    let values = samples.iter().map(|&s| s as Num / max as Num).collect();
+    Ok((width, height, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_samples_stay_inside_the_polygon() {
+        let square = Aperture::Polygon {
+            blades: 4,
+            rotation: consts::PI / 4.,
+        };
+        for k in 0..64 {
+            let u = ((k % 8) as Num / 8. + 0.01, (k / 8) as Num / 8. + 0.01);
+            let p = square.sample(u);
+            // the inscribed square has its edges at 1/√2
+            let edge = consts::FRAC_1_SQRT_2 + 1e-6;
+            assert!(p.x.abs() <= edge && p.y.abs() <= edge);
+        }
+    }
+
+    #[test]
+    fn cdf_sampling_follows_the_values() {
+        let cdf = [0., 1., 1., 4.];
+        assert_eq!(sample_cdf(&cdf, 0.), (0, 0.));
+        assert_eq!(sample_cdf(&cdf, 0.125), (0, 0.5));
+        // the empty bucket is never picked
+        assert_eq!(sample_cdf(&cdf, 0.25), (2, 0.));
+        assert_eq!(sample_cdf(&cdf, 0.625), (2, 0.5));
+    }
+
+    #[test]
+    fn mask_samples_only_open_pixels() {
+        // only the bottom right pixel is open
+        let mask = ApertureMask::new(2, 2, &[0., 0., 0., 1.]).unwrap();
+        for k in 0..16 {
+            let p = mask.sample((k as Num / 16., (15 - k) as Num / 16.));
+            assert!(p.x >= 0. && p.y <= 0.);
+        }
+        assert!(ApertureMask::new(2, 2, &[0.; 4]).is_err());
+    }
+
+    #[test]
+    fn reads_pgm_images() {
+        let (w, h, values) = read_pgm(b"P2\n# mask\n2 1\n4\n0 4\n").unwrap();
+        assert_eq!((w, h, values), (2, 1, vec![0., 1.]));
+        let (w, h, values) = read_pgm(b"P5 1 2 255\n\xff\x00").unwrap();
+        assert_eq!((w, h, values), (1, 2, vec![1., 0.]));
+    }
+
+    #[test]
+    fn rejects_oversized_pgm_headers() {
+        for header in [
+            &b"P5 4294967296 4294967296 255\n\x00"[..],
+            b"P2 100000 100000 255\n0 0",
+            b"P5 2 2 65535\n\x00\x00\x00",
+        ] {
+            let err = read_pgm(header).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use super::{Aperture, Camera, Crop, LensSystem, Projection, Stereo};
+use crate::{
+    cancel::CancelToken,
+    sampler::SamplerKind,
+    stats::ProgressObserver,
+    vec3::{Point3, Vec3},
+    Num,
+};
+
+// Chainable configuration of a Camera, `build` returns it initialized.
+//
+//     let cam = Camera::builder()
+//         .image_width(400)
+//         .lookfrom(Point3::new(13., 2., 3.))
+//         .build();
+pub struct CameraBuilder {
+    cam: Camera,
+}
+
+macro_rules! setters {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            pub fn $field(mut self, $field: $ty) -> Self {
+                self.cam.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+impl CameraBuilder {
+    pub fn new() -> CameraBuilder {
+        CameraBuilder {
+            cam: Camera::default(),
+        }
+    }
+
+    setters! {
+        aspect_ratio: Num,
+        image_width: i32,
+        resolution_percent: Num,
+        samples_per_pixel: i32,
+        max_depth: i32,
+        russian_roulette_depth: i32,
+        adaptive_threshold: Num,
+        min_samples_per_pixel: i32,
+        pass_samples: i32,
+        cancel_token: CancelToken,
+        progress_interval: Duration,
+        checkpoint_interval: Duration,
+        resume: bool,
+        projection: Projection,
+        vfov: Num,
+        lookfrom: Point3,
+        lookat: Point3,
+        vup: Vec3,
+        defocus_angle: Num,
+        focus_dist: Num,
+        aperture: Aperture,
+        lens_shift: (Num, Num),
+        lens_tilt: (Num, Num),
+        sampler: SamplerKind,
+        seed: u64,
+        threads: usize,
+    }
+
+    pub fn snapshot_interval(mut self, interval: Duration) -> Self {
+        self.cam.snapshot_interval = Some(interval);
+        self
+    }
+
+    pub fn time_budget(mut self, budget: Duration) -> Self {
+        self.cam.time_budget = Some(budget);
+        self
+    }
+
+    pub fn crop(mut self, crop: Crop) -> Self {
+        self.cam.crop = Some(crop);
+        self
+    }
+
+    pub fn stereo(mut self, stereo: Stereo) -> Self {
+        self.cam.stereo = Some(stereo);
+        self
+    }
+
+    pub fn lens(mut self, lens: LensSystem) -> Self {
+        self.cam.lens = Some(lens);
+        self
+    }
+
+    pub fn progress(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.cam.progress = Some(observer);
+        self
+    }
+
+    pub fn checkpoint_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.cam.checkpoint_path = Some(path.into());
+        self
+    }
+
+    pub fn build(mut self) -> Camera {
+        self.cam.initialize();
+        self.cam
+    }
+}
+
+impl Default for CameraBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_initializes_the_camera() {
+        let cam = CameraBuilder::new()
+            .aspect_ratio(2.)
+            .image_width(40)
+            .samples_per_pixel(3)
+            .build();
+        assert_eq!(cam.samples_per_pixel, 3);
+        assert_eq!(cam.image_size(), (40, 20));
+        assert_eq!(cam.crop_rect(), (0, 0, 40, 20));
+    }
+}
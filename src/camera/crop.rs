@@ -0,0 +1,73 @@
+use crate::Num;
+
+// Part of the image to render
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Crop {
+    // Rectangle in pixels from the top left corner of the image, after
+    // `resolution_percent` is applied
+    Pixels {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    // Corners as fractions of the image size, independent of the resolution
+    Normalized {
+        x0: Num,
+        y0: Num,
+        x1: Num,
+        y1: Num,
+    },
+}
+
+impl Crop {
+    // Pixel rectangle (x, y, width, height) inside an image of the given
+    // size, at least one pixel large
+    pub(crate) fn rect(&self, width: i32, height: i32) -> (i32, i32, i32, i32) {
+        let (x0, y0, x1, y1) = match *self {
+            Self::Pixels {
+                x,
+                y,
+                width,
+                height,
+            } => (x, y, x.saturating_add(width), y.saturating_add(height)),
+            Self::Normalized { x0, y0, x1, y1 } => (
+                (x0 * width as Num).floor() as i32,
+                (y0 * height as Num).floor() as i32,
+                (x1 * width as Num).ceil() as i32,
+                (y1 * height as Num).ceil() as i32,
+            ),
+        };
+        let x0 = x0.clamp(0, width - 1);
+        let y0 = y0.clamp(0, height - 1);
+        let x1 = x1.clamp(x0 + 1, width);
+        let y1 = y1.clamp(y0 + 1, height);
+        (x0, y0, x1 - x0, y1 - y0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rect_is_clamped_to_the_image() {
+        let pixels = |x, y, width, height| Crop::Pixels {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(pixels(2, 3, 4, 5).rect(10, 10), (2, 3, 4, 5));
+        assert_eq!(pixels(-5, 8, 20, 20).rect(10, 10), (0, 8, 10, 2));
+        assert_eq!(pixels(i32::MAX, 0, i32::MAX, 0).rect(10, 10), (9, 0, 1, 1));
+        assert_eq!(pixels(4, 4, i32::MAX, -3).rect(10, 10), (4, 4, 6, 1));
+        let corners = Crop::Normalized {
+            x0: 0.25,
+            y0: -1.,
+            x1: 0.6,
+            y1: 2.,
+        };
+        assert_eq!(corners.rect(10, 4), (2, 0, 4, 4));
+    }
+}
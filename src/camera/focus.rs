@@ -0,0 +1,101 @@
+use super::{lens::MM, Camera, Projection};
+use crate::{
+    hittable::{hittable_list::HittableList, Hittable},
+    interval::Interval,
+    ray::Ray,
+    vec3::*,
+    Num,
+};
+
+// What the camera focuses on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FocusTarget {
+    Lookat,          // Whatever is seen in the direction of `lookat`
+    Pixel(i32, i32), // Whatever is seen through the center of an image pixel
+}
+
+impl Camera {
+    // Sets `focus_dist` to the distance of the first surface seen through the
+    // target and returns it. When the ray escapes the scene the focus is left
+    // unchanged and None is returned.
+    pub fn focus_on(&mut self, world: &HittableList, target: FocusTarget) -> Option<Num> {
+        self.initialize();
+        let r = self.focus_ray(target)?;
+        let rec = world.hit(&r, Interval::new(0.001, Ray::INFINITY))?;
+
+        // the focus plane is perpendicular to the view direction
+        let distance = dot(&(rec.p - self.center), &-self.w);
+        if distance <= 0. {
+            return None;
+        }
+        self.focus_dist = distance;
+        self.initialize();
+        Some(distance)
+    }
+
+    // Ray through the center of the lens towards the target
+    fn focus_ray(&self, target: FocusTarget) -> Option<Ray> {
+        let (i, j) = match target {
+            FocusTarget::Lookat => return Some(Ray::new(self.center, self.lookat - self.center)),
+            FocusTarget::Pixel(i, j) => (i, j),
+        };
+        if !(0..self.render_width).contains(&i) || !(0..self.image_height).contains(&j) {
+            return None;
+        }
+        let (_, i, j) = self.view_pixel(i, j)?;
+        let s = (i as Num + 0.5) / self.view_width as Num;
+        let t = (j as Num + 0.5) / self.view_height as Num;
+
+        if let Some(lens) = &self.focused_lens {
+            let (o, d, _) = lens.sample_ray(s, t, (0.5, 0.5))?;
+            let origin = self.center + MM * (o.x * self.u + o.y * self.v - o.z * self.w);
+            return Some(Ray::new(origin, d.x * self.u + d.y * self.v - d.z * self.w));
+        }
+        if self.projection.is_panoramic() {
+            let aspect = self.view_width as Num / self.view_height as Num;
+            let [x, y, z] = self.projection.panoramic_direction(s, t, aspect)?;
+            return Some(Ray::new(self.center, x * self.u + y * self.v - z * self.w));
+        }
+
+        let pixel_center =
+            self.pixel00_loc + (i as Num * self.pixel_delta_u) + (j as Num * self.pixel_delta_v);
+        Some(match self.projection {
+            Projection::Orthographic { .. } => {
+                Ray::new(pixel_center + self.focus_dist * self.w, -self.w)
+            }
+            _ => Ray::new(self.center, pixel_center - self.center),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::tests::{test_camera, test_scene};
+
+    #[test]
+    fn focuses_on_the_first_surface() {
+        let world = test_scene();
+        let mut cam = test_camera().build();
+        // the front of the sphere at z = -1 seen from z = 1
+        let distance = cam.focus_on(&world, FocusTarget::Lookat).unwrap();
+        assert!((distance - 1.5).abs() < 1e-6);
+        assert_eq!(cam.focus_dist, distance);
+
+        // the ground half a unit below the camera, seen about 45° down
+        let ground = cam.focus_on(&world, FocusTarget::Pixel(8, 15)).unwrap();
+        assert!(ground > 0.5 && ground < 0.6);
+    }
+
+    #[test]
+    fn pixels_outside_the_image_are_rejected() {
+        let world = test_scene();
+        let mut cam = test_camera().build();
+        for (i, j) in [(-1, 8), (8, -1), (16, 8), (8, 16)] {
+            assert_eq!(cam.focus_on(&world, FocusTarget::Pixel(i, j)), None);
+        }
+        // the sky is not a surface to focus on either
+        assert_eq!(cam.focus_on(&world, FocusTarget::Pixel(0, 0)), None);
+        assert_eq!(cam.focus_dist, 2.);
+    }
+}
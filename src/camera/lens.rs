@@ -0,0 +1,395 @@
+use std::{fs, io, path::Path};
+
+use crate::{sampler::halton::radical_inverse, vec3::*, Num};
+
+// Lens data is in millimeters, scenes in meters
+pub(crate) const MM: Num = 0.001;
+
+// One refracting surface of a lens prescription
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LensElement {
+    pub radius: Num, // Curvature radius, positive when curving away from the scene, 0 for the aperture stop
+    pub thickness: Num, // Distance along the axis to the next surface, or to the film for the last one
+    pub ior: Num,       // Index of refraction behind the surface, 0 for air
+    pub aperture: Num,  // Diameter of the surface
+}
+
+// Tabulated lens, surfaces listed from the front (scene side) to the rear
+#[derive(Clone, PartialEq, Debug)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+    pub film_diagonal: Num, // Diagonal of the film in millimeters, sets the field of view
+}
+
+// Double Gauss 50mm f/2 lens from "Modern Lens Design" (Smith, p.312)
+const DOUBLE_GAUSS_50MM: &str = "
+# radius thickness ior aperture
+29.475   3.76   1.67   25.2
+84.83    0.12   1      25.2
+19.275   4.025  1.67   23
+40.77    3.275  1.699  23
+12.75    5.705  1      18
+0        4.5    0      17.1
+-14.495  1.18   1.603  17
+40.77    6.065  1.658  20
+-20.385  0.19   1      20
+437.065  3.22   1.717  20
+-39.73   5      1      20
+";
+
+impl LensSystem {
+    // Full frame 35mm film
+    pub const FULL_FRAME_DIAGONAL: Num = 43.27;
+
+    // Prescription in the usual lens data layout, one surface per line as
+    // `radius thickness ior aperture` in millimeters. `#` starts a comment.
+    pub fn parse(text: &str) -> io::Result<LensSystem> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut elements = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values = line
+                .split_whitespace()
+                .map(|v| v.parse::<Num>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| invalid(format!("line {}: {}", n + 1, e)))?;
+            let [radius, thickness, ior, aperture] = values[..] else {
+                return Err(invalid(format!(
+                    "line {}: expected 4 values, found {}",
+                    n + 1,
+                    values.len()
+                )));
+            };
+            elements.push(LensElement {
+                radius,
+                thickness,
+                ior,
+                aperture,
+            });
+        }
+        if elements.is_empty() {
+            return Err(invalid(String::from("no lens surfaces")));
+        }
+        Ok(LensSystem {
+            elements,
+            film_diagonal: Self::FULL_FRAME_DIAGONAL,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<LensSystem> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn double_gauss_50mm() -> LensSystem {
+        Self::parse(DOUBLE_GAUSS_50MM).unwrap()
+    }
+}
+
+const PUPIL_BINS: usize = 64;
+const PUPIL_SAMPLES: u64 = 4096;
+
+// Box on the plane of the rear surface, (min x, max x, min y, max y)
+type Bounds = [Num; 4];
+
+fn area(b: &Bounds) -> Num {
+    (b[1] - b[0]) * (b[3] - b[2])
+}
+
+// Area of the exit pupil at the image center, or of the largest one when
+// the center is vignetted. None when every pupil is.
+fn reference_area(pupils: &[Option<Bounds>]) -> Option<Num> {
+    let center = pupils.first()?.as_ref().map_or(0., area);
+    if center > 0. {
+        return Some(center);
+    }
+    let largest = pupils.iter().flatten().map(area).fold(0., Num::max);
+    (largest > 0.).then_some(largest)
+}
+
+// Lens system focused at a distance, with the exit pupil of every film
+// radius precomputed. Lens space has the film at z = 0 and the scene
+// towards +z, in millimeters.
+pub(crate) struct FocusedLens {
+    elements: Vec<LensElement>,
+    vertex_z: Vec<Num>, // Position of each surface on the axis
+    film: (Num, Num),   // Film width and height
+    // Region of the rear surface rays pass through, for film points on the
+    // +x axis in each radius bin. None when the whole bin is vignetted.
+    pupils: Vec<Option<Bounds>>,
+    reference_area: Num, // Pupil area that gets a weight of 1
+}
+
+impl FocusedLens {
+    // None when no light reaches the film through the lens
+    pub(crate) fn new(
+        system: &LensSystem,
+        focus_distance: Num,
+        aspect: Num,
+    ) -> Option<FocusedLens> {
+        let height = system.film_diagonal / (1. + aspect * aspect).sqrt();
+        let mut lens = FocusedLens {
+            elements: system.elements.clone(),
+            vertex_z: Vec::new(),
+            film: (aspect * height, height),
+            pupils: Vec::new(),
+            reference_area: 0.,
+        };
+        lens.place_elements();
+        lens.focus(focus_distance);
+        lens.pupils = (0..PUPIL_BINS).map(|b| lens.exit_pupil(b)).collect();
+        lens.reference_area = reference_area(&lens.pupils)?;
+        Some(lens)
+    }
+
+    fn place_elements(&mut self) {
+        let mut z = 0.;
+        self.vertex_z = vec![0.; self.elements.len()];
+        for (e, vz) in self.elements.iter().zip(self.vertex_z.iter_mut()).rev() {
+            z += e.thickness;
+            *vz = z;
+        }
+    }
+
+    // Moves the lens along the axis until a point at `distance` in front of
+    // the film is imaged onto it
+    fn focus(&mut self, distance: Num) {
+        for _ in 0..32 {
+            let Some(image_z) = self.image_z(distance) else {
+                log::warn!("the lens cannot focus at {}mm", distance);
+                return;
+            };
+            self.elements.last_mut().unwrap().thickness -= image_z;
+            self.place_elements();
+            if image_z.abs() < 1e-9 {
+                return;
+            }
+        }
+    }
+
+    // Where a paraxial ray from the axis point at `distance` crosses the axis
+    // again behind the lens
+    fn image_z(&self, distance: Num) -> Option<Num> {
+        let front = &self.elements[0];
+        let target = Point3::new(0.01 * front.aperture / 2., 0., self.vertex_z[0]);
+        let origin = Point3::new(0., 0., distance);
+        let (p, d) = self.trace_from_scene(origin, target - origin)?;
+        if d.x == 0. {
+            return None;
+        }
+        Some(p.z - p.x / d.x * d.z)
+    }
+
+    fn exit_pupil(&self, bin: usize) -> Option<Bounds> {
+        let film_radius = self.film_radius();
+        let r0 = bin as Num / PUPIL_BINS as Num * film_radius;
+        let r1 = (bin + 1) as Num / PUPIL_BINS as Num * film_radius;
+        let rear = self.elements.len() - 1;
+        let extent = 1.5 * self.elements[rear].aperture / 2.;
+
+        let mut bounds: Option<Bounds> = None;
+        for i in 0..PUPIL_SAMPLES {
+            let f = (i as Num + 0.5) / PUPIL_SAMPLES as Num;
+            let film = Point3::new(r0 + f * (r1 - r0), 0., 0.);
+            let x = -extent + 2. * extent * radical_inverse(2, i);
+            let y = -extent + 2. * extent * radical_inverse(3, i);
+            let inside = bounds.is_some_and(|b| b[0] <= x && x <= b[1] && b[2] <= y && y <= b[3]);
+            if !inside {
+                let rear_point = Point3::new(x, y, self.vertex_z[rear]);
+                if self.trace_from_film(film, rear_point - film).is_none() {
+                    continue;
+                }
+            }
+            bounds = Some(match bounds {
+                Some(b) => [b[0].min(x), b[1].max(x), b[2].min(y), b[3].max(y)],
+                None => [x, x, y, y],
+            });
+        }
+        // grow by the sample spacing so the pupil is not cut short
+        let margin = 2. * extent / (PUPIL_SAMPLES as Num).sqrt();
+        bounds.map(|b| [b[0] - margin, b[1] + margin, b[2] - margin, b[3] + margin])
+    }
+
+    fn film_radius(&self) -> Num {
+        (self.film.0 * self.film.0 + self.film.1 * self.film.1).sqrt() / 2.
+    }
+
+    // Ray leaving the front of the lens for image position `(s, t)`, both in
+    // [0,1) from the top left corner, and its weight. The image on the film is
+    // upside down. The weight falls off towards the edges like a real lens
+    // (cos⁴ and pupil size) and is 1 at the center.
+    pub(crate) fn sample_ray(&self, s: Num, t: Num, u: (Num, Num)) -> Option<(Point3, Vec3, Num)> {
+        let film = Point3::new((0.5 - s) * self.film.0, (t - 0.5) * self.film.1, 0.);
+        let r = (film.x * film.x + film.y * film.y).sqrt();
+        let bin = Num::min(
+            r / self.film_radius() * PUPIL_BINS as Num,
+            (PUPIL_BINS - 1) as Num,
+        );
+        let bounds = self.pupils[bin as usize].as_ref()?;
+
+        // the pupil was found for points on +x, rotate it to the film point
+        let x = bounds[0] + u.0 * (bounds[1] - bounds[0]);
+        let y = bounds[2] + u.1 * (bounds[3] - bounds[2]);
+        let (sin, cos) = if r > 0. {
+            (film.y / r, film.x / r)
+        } else {
+            (0., 1.)
+        };
+        let rear_point = Point3::new(
+            cos * x - sin * y,
+            sin * x + cos * y,
+            self.vertex_z[self.elements.len() - 1],
+        );
+
+        let direction = rear_point - film;
+        let (origin, out) = self.trace_from_film(film, direction)?;
+        let cos_theta = normalize(&direction).z;
+        let weight = cos_theta.powi(4) * area(bounds) / self.reference_area;
+        Some((origin, out, weight))
+    }
+
+    fn trace_from_film(&self, origin: Point3, direction: Vec3) -> Option<(Point3, Vec3)> {
+        let (mut o, mut d) = (origin, normalize(&direction));
+        for idx in (0..self.elements.len()).rev() {
+            // from the medium behind the surface into the one in front of it
+            let eta_t = if idx == 0 { 1. } else { self.medium(idx - 1) };
+            (o, d) = self.cross_surface(idx, o, d, self.medium(idx) / eta_t)?;
+        }
+        Some((o, d))
+    }
+
+    fn trace_from_scene(&self, origin: Point3, direction: Vec3) -> Option<(Point3, Vec3)> {
+        let (mut o, mut d) = (origin, normalize(&direction));
+        for idx in 0..self.elements.len() {
+            let eta_i = if idx == 0 { 1. } else { self.medium(idx - 1) };
+            (o, d) = self.cross_surface(idx, o, d, eta_i / self.medium(idx))?;
+        }
+        Some((o, d))
+    }
+
+    fn medium(&self, idx: usize) -> Num {
+        let ior = self.elements[idx].ior;
+        if ior == 0. {
+            1.
+        } else {
+            ior
+        }
+    }
+
+    // Intersects surface `idx` and refracts through it, None when the ray
+    // misses its aperture or is totally reflected
+    fn cross_surface(&self, idx: usize, o: Point3, d: Vec3, eta: Num) -> Option<(Point3, Vec3)> {
+        let e = &self.elements[idx];
+        let vz = self.vertex_z[idx];
+        if e.radius == 0. {
+            // aperture stop
+            if d.z == 0. {
+                return None;
+            }
+            let t = (vz - o.z) / d.z;
+            let p = o + t * d;
+            return (t > 0. && p.x * p.x + p.y * p.y <= e.aperture * e.aperture / 4.)
+                .then_some((p, d));
+        }
+
+        let center = Point3::new(0., 0., vz - e.radius);
+        let oc = o - center;
+        let half_b = dot(&oc, &d);
+        let c = oc.lenght_sqr() - e.radius * e.radius;
+        let discriminant = half_b * half_b - c;
+        if discriminant < 0. {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+        // the surface is the cap of the sphere on the side of the vertex
+        let p = [-half_b - sqrtd, -half_b + sqrtd]
+            .into_iter()
+            .filter(|&t| t > 1e-9)
+            .map(|t| o + t * d)
+            .find(|p| (p.z - center.z) * e.radius > 0.)?;
+        if p.x * p.x + p.y * p.y > e.aperture * e.aperture / 4. {
+            return None;
+        }
+
+        let mut n = (p - center) / e.radius.abs();
+        if dot(&n, &d) > 0. {
+            n = -n;
+        }
+        let cos_i = -dot(&d, &n);
+        let sin2_t = eta * eta * (1. - cos_i * cos_i);
+        if sin2_t > 1. {
+            return None;
+        }
+        let cos_t = (1. - sin2_t).sqrt();
+        Some((p, eta * d + (eta * cos_i - cos_t) * n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_surfaces_and_rejects_bad_lines() {
+        let lens = LensSystem::parse("# comment\n 10 2 1.5 8 # front\n\n0 1 0 6\n").unwrap();
+        assert_eq!(lens.elements.len(), 2);
+        assert_eq!(lens.elements[0].ior, 1.5);
+        assert_eq!(lens.elements[1].radius, 0.);
+
+        for bad in ["", "10 2 1.5", "10 2 x 8"] {
+            let err = LensSystem::parse(bad).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn lens_focuses_at_the_requested_distance() {
+        let system = LensSystem::double_gauss_50mm();
+        for distance in [500., 2000., 10000.] {
+            let lens = FocusedLens::new(&system, distance, 1.5).unwrap();
+            assert!(lens.image_z(distance).unwrap().abs() < 1e-3);
+        }
+        // closer subjects need the lens further from the film
+        let near = FocusedLens::new(&system, 500., 1.5).unwrap();
+        let far = FocusedLens::new(&system, 10000., 1.5).unwrap();
+        assert!(near.vertex_z[0] > far.vertex_z[0]);
+    }
+
+    #[test]
+    fn rays_leave_forward_and_darken_towards_the_corners() {
+        let lens = FocusedLens::new(&LensSystem::double_gauss_50mm(), 1000., 1.5).unwrap();
+        let mean_weight = |s, t| {
+            let (mut sum, mut hits) = (0., 0);
+            for i in 0..256 {
+                let u = (radical_inverse(2, i), radical_inverse(3, i));
+                if let Some((o, d, weight)) = lens.sample_ray(s, t, u) {
+                    // on the front surface, which bulges up to 3mm behind its vertex
+                    assert!((o.z - lens.vertex_z[0]).abs() < 3. && d.z > 0.);
+                    assert!(weight.is_finite() && weight > 0.);
+                    sum += weight;
+                    hits += 1;
+                }
+            }
+            sum / hits.max(1) as Num
+        };
+        // cos⁴ of the angles to the rear surface, slightly below 1
+        let center = mean_weight(0.5, 0.5);
+        assert!(center > 0.9 && center <= 1.);
+        assert!(mean_weight(0.02, 0.02) < center);
+    }
+
+    #[test]
+    fn vignetted_center_falls_back_to_the_largest_pupil() {
+        let small = Some([0., 1., 0., 1.]);
+        let large = Some([0., 2., 0., 2.]);
+        assert_eq!(reference_area(&[small, large]), Some(1.));
+        assert_eq!(reference_area(&[None, small, large]), Some(4.));
+        assert_eq!(reference_area(&[None, None]), None);
+
+        let mut closed = LensSystem::double_gauss_50mm();
+        closed.elements[5].aperture = 0.;
+        assert!(FocusedLens::new(&closed, 1000., 1.5).is_none());
+    }
+}
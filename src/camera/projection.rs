@@ -0,0 +1,189 @@
+use crate::{consts, Num};
+
+// How pixels are mapped to rays leaving the camera
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+    // Rays fan out from `lookfrom`, covering `vfov` vertically
+    Perspective,
+    // Parallel rays along the view direction, from a view plane `height`
+    // world units tall centered on `lookfrom`
+    Orthographic { height: Num },
+
+    // The panoramic projections below trace from `lookfrom` without depth of
+    // field, `vfov`, `focus_dist` and `defocus_angle` are ignored.
+
+    // Circular image inscribed in the image height, covering `fov` radians
+    // across its diameter, up to 2π, larger angles are clamped to it. Pixels
+    // outside the circle stay black.
+    Fisheye { fov: Num, mapping: FisheyeMapping },
+    // Full sphere of directions, longitude across and latitude down the
+    // image, which should have a 2:1 aspect ratio
+    Equirectangular,
+    // Six 90° faces laid out as 3x2 tiles, which should have a 3:2 aspect
+    // ratio. Top row: right, left, up. Bottom row: down, front, back.
+    Cubemap,
+}
+
+// Distance from the fisheye image center as a function of the angle to the
+// view direction
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FisheyeMapping {
+    // Proportional to the angle
+    Equidistant,
+    // Preserves solid angles, so every pixel sees the same area of the sphere
+    Equisolid,
+}
+
+impl Projection {
+    pub fn is_panoramic(&self) -> bool {
+        !matches!(self, Self::Perspective | Self::Orthographic { .. })
+    }
+
+    // Direction through image position `(s, t)`, both in [0,1) from the top
+    // left corner, in camera space (right, up, forward). None when the
+    // position is not covered by the projection.
+    pub(crate) fn panoramic_direction(&self, s: Num, t: Num, aspect: Num) -> Option<[Num; 3]> {
+        match *self {
+            Self::Fisheye { fov, mapping } => {
+                // beyond a full turn the mappings fold over
+                let fov = fov.min(2. * consts::PI);
+                // unit radius is half the image height
+                let x = (2. * s - 1.) * aspect;
+                let y = 1. - 2. * t;
+                let r = (x * x + y * y).sqrt();
+                if r > 1. {
+                    return None;
+                }
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * fov / 2.,
+                    FisheyeMapping::Equisolid => 2. * (r * (fov / 4.).sin()).asin(),
+                };
+                let phi = y.atan2(x);
+                Some([
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                ])
+            }
+            Self::Equirectangular => {
+                let phi = (s - 0.5) * 2. * consts::PI;
+                let theta = (0.5 - t) * consts::PI;
+                Some([
+                    theta.cos() * phi.sin(),
+                    theta.sin(),
+                    theta.cos() * phi.cos(),
+                ])
+            }
+            Self::Cubemap => {
+                let col = Num::min(s * 3., 2.) as usize;
+                let row = Num::min(t * 2., 1.) as usize;
+                // position on the face in [-1,1], up is positive
+                let a = 2. * (s * 3. - col as Num) - 1.;
+                let b = 1. - 2. * (t * 2. - row as Num);
+                // forward, right and up axes of each face
+                let [f, r, u] = CUBE_FACES[col + 3 * row];
+                Some(std::array::from_fn(|k| f[k] + a * r[k] + b * u[k]))
+            }
+            Self::Perspective | Self::Orthographic { .. } => None,
+        }
+    }
+}
+
+type Axis = [Num; 3];
+const RIGHT: Axis = [1., 0., 0.];
+const LEFT: Axis = [-1., 0., 0.];
+const UP: Axis = [0., 1., 0.];
+const DOWN: Axis = [0., -1., 0.];
+const FRONT: Axis = [0., 0., 1.];
+const BACK: Axis = [0., 0., -1.];
+
+const CUBE_FACES: [[Axis; 3]; 6] = [
+    [RIGHT, BACK, UP],
+    [LEFT, FRONT, UP],
+    [UP, RIGHT, BACK],
+    [DOWN, RIGHT, FRONT],
+    [FRONT, RIGHT, UP],
+    [BACK, LEFT, UP],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(d: [Num; 3]) -> [Num; 3] {
+        let l = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        d.map(|v| v / l)
+    }
+
+    fn assert_near(d: Option<[Num; 3]>, expected: [Num; 3], eps: Num) {
+        let d = unit(d.unwrap());
+        for k in 0..3 {
+            assert!(
+                (d[k] - expected[k]).abs() < eps,
+                "{:?} != {:?}",
+                d,
+                expected
+            );
+        }
+    }
+
+    fn assert_dir(d: Option<[Num; 3]>, expected: [Num; 3]) {
+        assert_near(d, expected, 1e-5);
+    }
+
+    #[test]
+    fn equirectangular_covers_the_sphere() {
+        let p = Projection::Equirectangular;
+        assert_dir(p.panoramic_direction(0.5, 0.5, 2.), FRONT);
+        assert_dir(p.panoramic_direction(0.75, 0.5, 2.), RIGHT);
+        assert_dir(p.panoramic_direction(0., 0.5, 2.), BACK);
+        assert_dir(p.panoramic_direction(0.5, 0., 2.), UP);
+    }
+
+    #[test]
+    fn fisheye_edge_is_at_half_the_fov() {
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let p = Projection::Fisheye {
+                fov: consts::PI,
+                mapping,
+            };
+            assert_dir(p.panoramic_direction(0.5, 0.5, 1.), FRONT);
+            assert_dir(p.panoramic_direction(1., 0.5, 1.), RIGHT);
+            assert_dir(p.panoramic_direction(0.5, 0., 1.), UP);
+            assert!(p.panoramic_direction(0.95, 0.95, 1.).is_none());
+        }
+    }
+
+    #[test]
+    fn fisheye_fov_stops_at_a_full_turn() {
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let p = Projection::Fisheye {
+                fov: 5. * consts::PI,
+                mapping,
+            };
+            assert_dir(p.panoramic_direction(0.5, 0.5, 1.), FRONT);
+            assert_near(p.panoramic_direction(1., 0.5, 1.), BACK, 1e-3);
+        }
+    }
+
+    #[test]
+    fn cubemap_faces_are_in_layout_order() {
+        let p = Projection::Cubemap;
+        let centers = [
+            (1. / 6., 0.25, RIGHT),
+            (0.5, 0.25, LEFT),
+            (5. / 6., 0.25, UP),
+            (1. / 6., 0.75, DOWN),
+            (0.5, 0.75, FRONT),
+            (5. / 6., 0.75, BACK),
+        ];
+        for (s, t, axis) in centers {
+            assert_dir(p.panoramic_direction(s, t, 1.5), axis);
+        }
+        // neighbouring faces share their edges: the right edge of the front
+        // face is the left edge of the right face
+        let front_right = p.panoramic_direction(2. / 3. - 1e-4, 0.75, 1.5).unwrap();
+        let right_left = p.panoramic_direction(1e-4, 0.25, 1.5);
+        assert_near(right_left, unit(front_right), 1e-3);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::Num;
+
+// Renders a view for each eye into one image
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Stereo {
+    pub layout: StereoLayout,
+    // Distance between the eyes, along the camera `u` axis
+    pub interocular: Num,
+    // Distance at which both views line up (zero parallax), objects closer
+    // than this appear in front of the screen
+    pub convergence: Num,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StereoLayout {
+    SideBySide, // Left eye in the left half
+    TopBottom,  // Left eye in the top half
+}
+
+impl Stereo {
+    // Panics unless the convergence distance is positive
+    pub fn new(layout: StereoLayout, interocular: Num, convergence: Num) -> Stereo {
+        assert!(
+            convergence > 0.,
+            "stereo convergence must be positive, got {}",
+            convergence
+        );
+        Stereo {
+            layout,
+            interocular,
+            convergence,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        camera::tests::{test_camera, Centered},
+        ray::Ray,
+        vec3::{dot, normalize, Vec3},
+    };
+
+    #[test]
+    fn side_by_side_splits_the_image() {
+        let cam = test_camera()
+            .image_width(17)
+            .stereo(Stereo::new(StereoLayout::SideBySide, 0.1, 2.))
+            .build();
+        assert_eq!(cam.view_pixel(3, 5), Some((-1., 3, 5)));
+        assert_eq!(cam.view_pixel(11, 5), Some((1., 3, 5)));
+        // the odd column belongs to neither eye
+        assert_eq!(cam.view_pixel(16, 5), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "convergence")]
+    fn convergence_must_be_positive() {
+        Stereo::new(StereoLayout::SideBySide, 0.1, 0.);
+    }
+
+    #[test]
+    fn eyes_converge_at_the_convergence_distance() {
+        let cam = test_camera()
+            .stereo(Stereo::new(StereoLayout::TopBottom, 0.2, 2.))
+            .build();
+        // the same view pixel seen by the left and the right eye
+        let (left, _) = cam.get_ray(3, 2, &mut Centered).unwrap();
+        let (right, _) = cam.get_ray(3, 10, &mut Centered).unwrap();
+        let eyes = *right.origin() - *left.origin();
+        assert!((eyes.lenght() - 0.2).abs() < 1e-6);
+
+        // both rays pass through the same point at the convergence distance
+        let at_convergence = |r: &Ray| {
+            let forward = Vec3::new(0., 0., -1.);
+            let t = (2. - dot(&(*r.origin() - cam.lookfrom), &forward))
+                / dot(&normalize(r.direction()), &forward);
+            *r.origin() + t * normalize(r.direction())
+        };
+        let gap = at_convergence(&left) - at_convergence(&right);
+        assert!(gap.lenght() < 1e-5);
+    }
+}
@@ -0,0 +1,72 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+// Shared flag used to stop a render from another thread, e.g. a Ctrl-C
+// handler. Clones refer to the same flag.
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // Allow the token to be used for another render
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+// When a running render has to stop: on cancellation or past its deadline
+pub(crate) struct RenderLimit {
+    pub cancel: CancelToken,
+    pub deadline: Option<Instant>,
+}
+
+impl RenderLimit {
+    pub fn reached(&self) -> bool {
+        self.cancel.is_cancelled() || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn clones_share_the_flag() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        clone.cancel();
+        assert!(token.is_cancelled());
+        token.reset();
+        assert!(!clone.is_cancelled());
+    }
+
+    #[test]
+    fn limit_is_reached_past_the_deadline() {
+        let limit = |deadline| RenderLimit {
+            cancel: CancelToken::new(),
+            deadline,
+        };
+        assert!(!limit(None).reached());
+        assert!(!limit(Some(Instant::now() + Duration::from_secs(60))).reached());
+        assert!(limit(Some(Instant::now())).reached());
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    fmt::Debug,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    color::Color,
+    framebuffer::{Framebuffer, Pixel},
+    sampler::SamplerKind,
+    vec3::Vec3,
+    Num,
+};
+
+const MAGIC: &[u8; 8] = b"RTCKPT3\n";
+const HEADER_BYTES: u64 = 8 + 4 * 2 + 8 + 1 + 4 * 4 + 8;
+const PIXEL_BYTES: u64 = 8 * 5 + 4 + 1;
+
+// State of an interrupted progressive render. Every pixel sample draws from
+// a random stream keyed on the seed, pixel and sample index, so the sample
+// counts in `image` are also the position of each pixel's random stream,
+// as long as the sampler settings and the crop are the same. The
+// `fingerprint` identifies the scene and the camera the image was taken with.
+pub struct Checkpoint {
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub target_samples: i32, // Samples per pixel the render was started with
+    pub crop_origin: (i32, i32), // Top left corner of `image` in the whole frame
+    pub samples_per_pixel: i32, // Samples per pixel of the last finished pass
+    pub fingerprint: u64,
+    pub image: Framebuffer,
+}
+
+impl Checkpoint {
+    // Values are always stored as f64, whatever `Num` is.
+    // Written to a temporary file first and then renamed, so a crash while
+    // saving never leaves a broken checkpoint behind
+    #[allow(clippy::unnecessary_cast)]
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut tmp_path = path.as_os_str().to_owned();
+        tmp_path.push(".tmp");
+
+        let mut f = BufWriter::new(fs::File::create(&tmp_path)?);
+        f.write_all(MAGIC)?;
+        f.write_all(&self.image.width.to_le_bytes())?;
+        f.write_all(&self.image.height.to_le_bytes())?;
+        f.write_all(&self.seed.to_le_bytes())?;
+        f.write_all(&[sampler_id(self.sampler)])?;
+        f.write_all(&self.target_samples.to_le_bytes())?;
+        f.write_all(&self.crop_origin.0.to_le_bytes())?;
+        f.write_all(&self.crop_origin.1.to_le_bytes())?;
+        f.write_all(&self.samples_per_pixel.to_le_bytes())?;
+        f.write_all(&self.fingerprint.to_le_bytes())?;
+        for pixel in self.image.pixels.iter() {
+            for v in [
+                pixel.sum.x,
+                pixel.sum.y,
+                pixel.sum.z,
+                pixel.lum_mean,
+                pixel.lum_m2,
+            ] {
+                f.write_all(&(v as f64).to_le_bytes())?;
+            }
+            f.write_all(&pixel.samples.to_le_bytes())?;
+            f.write_all(&[pixel.converged as u8])?;
+        }
+        f.into_inner()?.sync_all()?;
+
+        fs::rename(tmp_path, path)
+    }
+
+    // Fails with `InvalidData` for anything that is not a complete checkpoint,
+    // the image size is checked against the file size before allocating it
+    pub fn load(path: &Path) -> io::Result<Checkpoint> {
+        let file = fs::File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut f = BufReader::new(file);
+
+        let mut magic = [0u8; 8];
+        f.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a render checkpoint"));
+        }
+        let width = read_i32(&mut f)?;
+        let height = read_i32(&mut f)?;
+        let seed = read_u64(&mut f)?;
+        let mut sampler = [0u8];
+        f.read_exact(&mut sampler)?;
+        let sampler = sampler_kind(sampler[0]).ok_or_else(|| invalid_data("unknown sampler"))?;
+        let target_samples = read_i32(&mut f)?;
+        let crop_origin = (read_i32(&mut f)?, read_i32(&mut f)?);
+        let samples_per_pixel = read_i32(&mut f)?;
+        let fingerprint = read_u64(&mut f)?;
+
+        if width <= 0 || height <= 0 {
+            return Err(invalid_data("invalid image size"));
+        }
+        let expected_len = (width as u64)
+            .checked_mul(height as u64)
+            .and_then(|n| n.checked_mul(PIXEL_BYTES))
+            .and_then(|n| n.checked_add(HEADER_BYTES));
+        if expected_len != Some(file_len) {
+            return Err(invalid_data("image size does not match the file size"));
+        }
+
+        let mut image = Framebuffer::new(width, height);
+        for pixel in image.pixels.iter_mut() {
+            let sum = Color::new(read_num(&mut f)?, read_num(&mut f)?, read_num(&mut f)?);
+            let lum_mean = read_num(&mut f)?;
+            let lum_m2 = read_num(&mut f)?;
+            let samples = read_i32(&mut f)?;
+            let mut converged = [0u8];
+            f.read_exact(&mut converged)?;
+            *pixel = Pixel {
+                sum,
+                samples,
+                lum_mean,
+                lum_m2,
+                converged: converged[0] != 0,
+            };
+        }
+
+        Ok(Checkpoint {
+            seed,
+            sampler,
+            target_samples,
+            crop_origin,
+            samples_per_pixel,
+            fingerprint,
+            image,
+        })
+    }
+}
+
+// Hash of the settings a checkpoint was rendered with. FNV-1a, so it stays
+// the same between builds and Rust versions, unlike the std hashers. Numbers
+// are hashed as f64 whatever `Num` is.
+pub(crate) struct Fingerprint(u64);
+
+impl Fingerprint {
+    pub fn new() -> Fingerprint {
+        Fingerprint(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        self
+    }
+
+    pub fn int(&mut self, v: i64) -> &mut Self {
+        self.bytes(&v.to_le_bytes())
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn num(&mut self, v: Num) -> &mut Self {
+        self.bytes(&(v as f64).to_le_bytes())
+    }
+
+    pub fn vec(&mut self, v: &Vec3) -> &mut Self {
+        self.num(v.x).num(v.y).num(v.z)
+    }
+
+    // Settings without a hash of their own, through their exact `Debug` output
+    pub fn debug<T: Debug>(&mut self, v: &T) -> &mut Self {
+        self.bytes(format!("{:?}", v).as_bytes())
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn sampler_id(kind: SamplerKind) -> u8 {
+    match kind {
+        SamplerKind::Independent => 0,
+        SamplerKind::Stratified => 1,
+        SamplerKind::Halton => 2,
+        SamplerKind::Sobol => 3,
+    }
+}
+
+fn sampler_kind(id: u8) -> Option<SamplerKind> {
+    match id {
+        0 => Some(SamplerKind::Independent),
+        1 => Some(SamplerKind::Stratified),
+        2 => Some(SamplerKind::Halton),
+        3 => Some(SamplerKind::Sobol),
+        _ => None,
+    }
+}
+
+fn read_i32<R: Read>(f: &mut R) -> io::Result<i32> {
+    let mut buf = [0u8; 4];
+    f.read_exact(&mut buf)?;
+    Ok(i32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(f: &mut R) -> io::Result<u64> {
+    let mut buf = [0u8; 8];
+    f.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn read_num<R: Read>(f: &mut R) -> io::Result<Num> {
+    let mut buf = [0u8; 8];
+    f.read_exact(&mut buf)?;
+    Ok(f64::from_le_bytes(buf) as Num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.ckpt", name, std::process::id()))
+    }
+
+    fn checkpoint() -> Checkpoint {
+        let mut image = Framebuffer::new(3, 2);
+        for (k, pixel) in image.pixels.iter_mut().enumerate() {
+            for s in 0..=k {
+                pixel.add_sample(&Color::new(0.1 * s as Num, 0.5, k as Num));
+            }
+            pixel.converged = k % 2 == 0;
+        }
+        Checkpoint {
+            seed: 42,
+            sampler: SamplerKind::Sobol,
+            target_samples: 64,
+            crop_origin: (10, 20),
+            samples_per_pixel: 5,
+            fingerprint: 0x1234_5678_9abc_def0,
+            image,
+        }
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = temp_path("round-trip");
+        let saved = checkpoint();
+        saved.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.seed, saved.seed);
+        assert_eq!(loaded.sampler, saved.sampler);
+        assert_eq!(loaded.target_samples, saved.target_samples);
+        assert_eq!(loaded.crop_origin, saved.crop_origin);
+        assert_eq!(loaded.samples_per_pixel, saved.samples_per_pixel);
+        assert_eq!(loaded.fingerprint, saved.fingerprint);
+        assert_eq!(
+            (loaded.image.width, loaded.image.height),
+            (saved.image.width, saved.image.height)
+        );
+        for (a, b) in loaded.image.pixels.iter().zip(saved.image.pixels.iter()) {
+            assert_eq!((a.sum.x, a.sum.y, a.sum.z), (b.sum.x, b.sum.y, b.sum.z));
+            assert_eq!((a.lum_mean, a.lum_m2), (b.lum_mean, b.lum_m2));
+            assert_eq!((a.samples, a.converged), (b.samples, b.converged));
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let path = temp_path("corrupt");
+        checkpoint().save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        let mut truncated = bytes.clone();
+        truncated.pop();
+        let mut negative = bytes.clone();
+        negative[8..12].copy_from_slice(&(-3i32).to_le_bytes());
+        let mut huge = bytes.clone();
+        huge[8..12].copy_from_slice(&i32::MAX.to_le_bytes());
+        huge[12..16].copy_from_slice(&i32::MAX.to_le_bytes());
+        let mut sampler = bytes.clone();
+        sampler[24] = 9;
+
+        for corrupt in [truncated, negative, huge, sampler, b"RTCKPT2\n".to_vec()] {
+            fs::write(&path, corrupt).unwrap();
+            let err = Checkpoint::load(&path).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn fingerprints_tell_settings_apart() {
+        let hash = |v: Num| Fingerprint::new().num(v).int(3).finish();
+        assert_eq!(hash(1.5), hash(1.5));
+        assert_ne!(hash(1.5), hash(-1.5));
+        assert_ne!(
+            Fingerprint::new().debug(&Some(2)).finish(),
+            Fingerprint::new().debug(&None::<i32>).finish()
+        );
+    }
+}
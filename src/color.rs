@@ -7,7 +7,16 @@ fn linear_to_gamma(linear_component: Num) -> Num {
     linear_component.sqrt()
 }
 
-pub fn write_color<W: std::io::Write>(f: &mut W, pixel_color: &Color, samplex_per_pixel: i32) {
+// Relative luminance of a linear color (Rec. 709)
+pub fn luminance(c: &Color) -> Num {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+pub fn write_color<W: std::io::Write>(
+    f: &mut W,
+    pixel_color: &Color,
+    samplex_per_pixel: i32,
+) -> std::io::Result<()> {
     let scale = 1.0 / samplex_per_pixel as Num;
     let r = linear_to_gamma(pixel_color.x * scale);
     let g = linear_to_gamma(pixel_color.y * scale);
@@ -30,5 +39,4 @@ pub fn write_color<W: std::io::Write>(f: &mut W, pixel_color: &Color, samplex_pe
         ((255.999 * INTENSITY.clamp(g)) as u8),
         ((255.999 * INTENSITY.clamp(b)) as u8),
     ])
-    .unwrap();
 }
@@ -0,0 +1,133 @@
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{color::write_color, framebuffer::Framebuffer, Num};
+
+// Encoders turning a linear Framebuffer into image files
+
+// 8 bit binary PPM, gamma 2
+pub fn write_ppm<W: Write>(image: &Framebuffer, f: &mut W) -> io::Result<()> {
+    write!(f, "P6\n{} {}\n255\n", image.width, image.height)?;
+    for pixel in image.pixels.iter() {
+        write_color(f, &pixel.sum, pixel.samples.max(1))?;
+    }
+    Ok(())
+}
+
+// Little endian Portable FloatMap, keeps the linear values unclamped
+#[allow(clippy::unnecessary_cast)]
+pub fn write_pfm<W: Write>(image: &Framebuffer, f: &mut W) -> io::Result<()> {
+    write!(f, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+    // rows are stored bottom to top
+    for j in (0..image.height).rev() {
+        for i in 0..image.width {
+            let c = image.color(i, j);
+            for v in [c.x, c.y, c.z] {
+                f.write_all(&(v as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Per-pixel sample counts as a binary PGM, brightest at `max_samples`
+pub fn write_heatmap<W: Write>(image: &Framebuffer, max_samples: i32, f: &mut W) -> io::Result<()> {
+    write!(f, "P5\n{} {}\n255\n", image.width, image.height)?;
+    let scale = 255. / max_samples.max(1) as Num;
+    let bytes: Vec<u8> = image
+        .pixels
+        .iter()
+        .map(|p| (p.samples as Num * scale).clamp(0., 255.) as u8)
+        .collect();
+    f.write_all(&bytes)
+}
+
+// Encode with the format matching the file extension (.ppm or .pfm)
+pub fn save(image: &Framebuffer, path: &Path) -> io::Result<()> {
+    let encode = match path.extension().and_then(|e| e.to_str()) {
+        Some("ppm") => write_ppm::<BufWriter<fs::File>>,
+        Some("pfm") => write_pfm::<BufWriter<fs::File>>,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported image format {}", path.display()),
+            ))
+        }
+    };
+
+    write_file(path, |f| encode(image, f))
+}
+
+// Write the sample count heatmap of `image` to a PGM file
+pub fn save_heatmap(image: &Framebuffer, max_samples: i32, path: &Path) -> io::Result<()> {
+    write_file(path, |f| write_heatmap(image, max_samples, f))
+}
+
+// Written to a temporary file first so a viewer never sees a partial image
+fn write_file(
+    path: &Path,
+    encode: impl FnOnce(&mut BufWriter<fs::File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut f = BufWriter::new(fs::File::create(&tmp_path)?);
+    encode(&mut f)?;
+    f.into_inner()?.sync_all()?;
+    fs::rename(tmp_path, path)
+}
+
+// Path of frame `n` of a sequence, `render.ppm` becomes `render_0007.ppm`
+pub fn frame_path(path: &Path, n: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}_{:04}", stem, n);
+    if let Some(ext) = path.extension() {
+        name = format!("{}.{}", name, ext.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn heatmap_scales_sample_counts() {
+        let mut image = Framebuffer::new(3, 1);
+        for (k, pixel) in image.pixels.iter_mut().enumerate() {
+            for _ in 0..k * 2 {
+                pixel.add_sample(&Color::ZERO);
+            }
+        }
+        let mut bytes = Vec::new();
+        write_heatmap(&image, 4, &mut bytes).unwrap();
+        assert_eq!(bytes, b"P5\n3 1\n255\n\x00\x7f\xff");
+    }
+
+    #[test]
+    fn frame_numbers_go_before_the_extension() {
+        assert_eq!(
+            frame_path(Path::new("out/render.ppm"), 7),
+            Path::new("out/render_0007.ppm")
+        );
+        assert_eq!(
+            frame_path(Path::new("render"), 12),
+            Path::new("render_0012")
+        );
+    }
+
+    #[test]
+    fn pfm_rows_are_bottom_up() {
+        let mut image = Framebuffer::new(1, 2);
+        image.pixels[0].add_sample(&Color::new(1., 1., 1.));
+        let mut bytes = Vec::new();
+        write_pfm(&image, &mut bytes).unwrap();
+        let header = b"PF\n1 2\n-1.0\n".len();
+        assert_eq!(bytes.len(), header + 2 * 12);
+        assert_eq!(&bytes[header..header + 4], &0f32.to_le_bytes());
+        assert_eq!(&bytes[header + 12..header + 16], &1f32.to_le_bytes());
+    }
+}
@@ -0,0 +1,117 @@
+use crate::{
+    color::{luminance, Color},
+    Num,
+};
+
+// Accumulated samples of a single pixel
+#[derive(Clone, Copy)]
+pub struct Pixel {
+    pub sum: Color,   // Sum of all sample colors
+    pub samples: i32, // Number of samples taken
+
+    // running mean and variance of the sample luminance (Welford),
+    // used by adaptive sampling
+    pub(crate) lum_mean: Num,
+    pub(crate) lum_m2: Num,
+    pub(crate) converged: bool,
+}
+
+impl Default for Pixel {
+    fn default() -> Self {
+        Pixel {
+            sum: Color::ZERO,
+            samples: 0,
+            lum_mean: 0.,
+            lum_m2: 0.,
+            converged: false,
+        }
+    }
+}
+
+impl Pixel {
+    pub fn add_sample(&mut self, c: &Color) {
+        self.sum += *c;
+        self.samples += 1;
+
+        let lum = luminance(c);
+        let delta = lum - self.lum_mean;
+        self.lum_mean += delta / self.samples as Num;
+        self.lum_m2 += delta * (lum - self.lum_mean);
+    }
+
+    // Average color of the samples taken so far
+    pub fn color(&self) -> Color {
+        if self.samples == 0 {
+            return Color::ZERO;
+        }
+        self.sum / self.samples as Num
+    }
+
+    // Half-width of the 95% confidence interval of the mean luminance,
+    // relative to the mean
+    pub fn relative_error(&self) -> Num {
+        if self.samples < 2 {
+            return Num::INFINITY;
+        }
+        let variance = self.lum_m2 / (self.samples - 1) as Num;
+        1.96 * (variance / self.samples as Num).sqrt() / Num::max(self.lum_mean, 1e-3)
+    }
+}
+
+// Linear image accumulated by the camera, stored row by row.
+// Use the `encode` module to write it to a file.
+pub struct Framebuffer {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<Pixel>,
+}
+
+impl Framebuffer {
+    pub fn new(width: i32, height: i32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Pixel::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn pixel(&self, i: i32, j: i32) -> &Pixel {
+        &self.pixels[(j * self.width + i) as usize]
+    }
+
+    // Copies `region` into this image with its top left corner at (x, y),
+    // parts outside the image are dropped
+    pub fn paste(&mut self, region: &Framebuffer, x: i32, y: i32) {
+        for j in 0..region.height {
+            for i in 0..region.width {
+                let (px, py) = (x + i, y + j);
+                if px < 0 || py < 0 || px >= self.width || py >= self.height {
+                    continue;
+                }
+                self.pixels[(py * self.width + px) as usize] = *region.pixel(i, j);
+            }
+        }
+    }
+
+    // Lowest sample count of any pixel
+    pub fn min_samples(&self) -> i32 {
+        self.pixels.iter().map(|p| p.samples).min().unwrap_or(0)
+    }
+
+    // Linear average color of a pixel
+    pub fn color(&self, i: i32, j: i32) -> Color {
+        self.pixel(i, j).color()
+    }
+
+    // Linear RGB of all pixels, row by row
+    #[allow(clippy::unnecessary_cast)]
+    pub fn to_rgb(&self) -> Vec<[f32; 3]> {
+        self.pixels
+            .iter()
+            .map(|p| {
+                let c = p.color();
+                [c.x as f32, c.y as f32, c.z as f32]
+            })
+            .collect()
+    }
+}
@@ -1,25 +1,26 @@
-use crate::{interval::Interval, material::*, ray::Ray, vec3::*, Num};
+use crate::{aabb::Aabb, interval::Interval, material::*, ray::Ray, vec3::*, Num};
 
 use self::sphere::Sphere;
 
 pub mod hittable_list;
 pub mod sphere;
 
-pub struct HitRecord {
+// Borrows the material from the object that was hit
+pub struct HitRecord<'a> {
     pub p: Point3,
     pub normal: Vec3,
-    pub mat: Box<Material>,
+    pub mat: &'a Material,
     pub t: Num,
     pub front_face: bool,
 }
-impl HitRecord {
-    pub fn new(_p: Point3, _t: Num, _m: Box<Material>) -> HitRecord {
+impl<'a> HitRecord<'a> {
+    pub fn new(_p: Point3, _t: Num, _m: &'a Material) -> HitRecord<'a> {
         HitRecord {
             p: _p,
             normal: Vec3 {
-                x: 0f64,
-                y: 0f64,
-                z: 0f64,
+                x: 0.,
+                y: 0.,
+                z: 0.,
             },
             mat: _m,
             t: _t,
@@ -27,7 +28,7 @@ impl HitRecord {
         }
     }
     pub fn set_face_normal(&mut self, r: &Ray, outward_normal: &Vec3) {
-        self.front_face = dot(r.direction(), outward_normal) < 0f64;
+        self.front_face = dot(r.direction(), outward_normal) < 0.;
         self.normal = if self.front_face {
             *outward_normal
         } else {
@@ -36,13 +37,66 @@ impl HitRecord {
     }
 }
 
-pub enum Hittable {
+// Geometry that rays can be traced against. Implement it to add custom
+// shapes and put them in a scene with `Primitive::Custom`.
+pub trait Hittable: Send + Sync {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>>;
+
+    // Box enclosing the whole object
+    fn bounding_box(&self) -> Aabb;
+
+    // Density of `random` sampling `direction` from `origin`, per solid angle.
+    // Only needed for objects that are sampled directly, e.g. lights.
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3) -> Num {
+        0.
+    }
+
+    // Direction from `origin` towards the object, from two uniform samples
+    fn random(&self, _origin: &Point3, _u: (Num, Num)) -> Vec3 {
+        Vec3::new(1., 0., 0.)
+    }
+}
+
+// Objects stored in a scene. The built-in shapes are matched directly,
+// avoiding the virtual call `Custom` shapes go through.
+pub enum Primitive {
     Sphere(Sphere),
+    Custom(Box<dyn Hittable>),
+}
+
+impl Primitive {
+    pub fn custom<H: Hittable + 'static>(object: H) -> Primitive {
+        Primitive::Custom(Box::new(object))
+    }
 }
-impl Hittable {
-    pub fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+
+impl Hittable for Primitive {
+    #[inline(always)]
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         match self {
             Self::Sphere(s) => s.hit(r, ray_t),
+            Self::Custom(h) => h.hit(r, ray_t),
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self {
+            Self::Sphere(s) => s.bounding_box(),
+            Self::Custom(h) => h.bounding_box(),
+        }
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> Num {
+        match self {
+            Self::Sphere(s) => s.pdf_value(origin, direction),
+            Self::Custom(h) => h.pdf_value(origin, direction),
+        }
+    }
+
+    fn random(&self, origin: &Point3, u: (Num, Num)) -> Vec3 {
+        match self {
+            Self::Sphere(s) => s.random(origin, u),
+            Self::Custom(h) => h.random(origin, u),
         }
     }
 }
@@ -1,31 +1,159 @@
-use crate::ray::Ray;
+use crate::{
+    checkpoint::Fingerprint,
+    ray::Ray,
+    simd::{Floats, RayPacket, LANES},
+    stats,
+};
 
 use super::*;
 
 pub struct HittableList {
-    objects: Vec<Hittable>,
+    objects: Vec<Primitive>,
+    bbox: Aabb,
+}
+
+impl Default for HittableList {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl HittableList {
     pub fn new() -> HittableList {
         HittableList {
             objects: Vec::new(),
+            bbox: Aabb::EMPTY,
         }
     }
 
     pub fn clear(&mut self) {
-        self.objects.clear()
+        self.objects.clear();
+        self.bbox = Aabb::EMPTY;
     }
 
-    pub fn add(&mut self, object: Hittable) {
+    pub fn add(&mut self, object: Primitive) {
+        self.bbox = Aabb::surrounding(&self.bbox, &object.bounding_box());
         self.objects.push(object);
     }
+
+    // Add a user-defined shape
+    pub fn add_custom<H: Hittable + 'static>(&mut self, object: H) {
+        self.add(Primitive::custom(object));
+    }
+
+    // Builder-style `add`, for constructing a scene in one expression
+    pub fn with(mut self, object: Primitive) -> Self {
+        self.add(object);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    // Closest hits of `LANES` rays, as `hit` would return them one by one.
+    // Spheres are tested against the whole packet, custom shapes one lane at
+    // a time for the lanes that hit their bounding box. Unlike `hit` the rays
+    // are not counted in the render statistics, callers record the lanes they
+    // use with `record_packet_lane`.
+    pub fn hit_packet(
+        &self,
+        rays: &[Ray; LANES],
+        ray_t: Interval,
+    ) -> [Option<HitRecord<'_>>; LANES] {
+        let mut hits = [const { None }; LANES];
+
+        let packet = RayPacket::new(rays);
+        let t_min = Floats::splat(ray_t.min);
+        let mut closest_so_far = Floats::splat(ray_t.max);
+        if !self.bbox.hit_packet(&packet, t_min, closest_so_far).any() {
+            return hits;
+        }
+
+        for object in self.objects.iter() {
+            match object {
+                Primitive::Sphere(s) => {
+                    let (mask, t) = s.hit_packet(&packet, t_min, closest_so_far);
+                    let bits = mask.bits();
+                    if bits == 0 {
+                        continue;
+                    }
+                    closest_so_far = t;
+                    let t = t.to_array();
+                    for (lane, hit) in hits.iter_mut().enumerate() {
+                        if bits & (1 << lane) != 0 {
+                            *hit = Some(s.record(&rays[lane], t[lane]));
+                        }
+                    }
+                }
+                Primitive::Custom(h) => {
+                    let bits = h
+                        .bounding_box()
+                        .hit_packet(&packet, t_min, closest_so_far)
+                        .bits();
+                    if bits == 0 {
+                        continue;
+                    }
+                    let mut closest = closest_so_far.to_array();
+                    for (lane, hit) in hits.iter_mut().enumerate() {
+                        if bits & (1 << lane) == 0 {
+                            continue;
+                        }
+                        let interval = Interval::new(ray_t.min, closest[lane]);
+                        if let Some(h) = h.hit(&rays[lane], interval) {
+                            closest[lane] = h.t;
+                            *hit = Some(h);
+                        }
+                    }
+                    closest_so_far = Floats::from_array(closest);
+                }
+            }
+        }
+        hits
+    }
+
+    // Custom shapes are only hashed by their bounding box
+    pub(crate) fn fingerprint(&self, f: &mut Fingerprint) {
+        f.int(self.objects.len() as i64);
+        for object in self.objects.iter() {
+            match object {
+                Primitive::Sphere(s) => s.fingerprint(f.int(0)),
+                Primitive::Custom(h) => {
+                    let bbox = h.bounding_box();
+                    f.int(1);
+                    for axis in [bbox.x, bbox.y, bbox.z] {
+                        f.num(axis.min).num(axis.max);
+                    }
+                }
+            }
+        }
+    }
+
+    // Counts one lane of a `hit_packet` call as a ray cast
+    pub(crate) fn record_packet_lane(&self) {
+        stats::record_ray(self.objects.len());
+    }
 }
 
-impl HittableList {
-    pub fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+impl FromIterator<Primitive> for HittableList {
+    fn from_iter<T: IntoIterator<Item = Primitive>>(iter: T) -> Self {
+        let mut list = HittableList::new();
+        for object in iter {
+            list.add(object);
+        }
+        list
+    }
+}
+
+impl Hittable for HittableList {
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let mut hit = Option::<HitRecord>::None;
         let mut closest_so_far = ray_t.max;
+        stats::record_ray(self.objects.len());
 
         for object in self.objects.iter() {
             if let Some(h) = object.hit(r, Interval::new(ray_t.min, closest_so_far)) {
@@ -35,4 +163,121 @@ impl HittableList {
         }
         hit
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // Objects are picked uniformly
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> Num {
+        if self.objects.is_empty() {
+            return 0.;
+        }
+        let sum: Num = self
+            .objects
+            .iter()
+            .map(|o| o.pdf_value(origin, direction))
+            .sum();
+        sum / self.objects.len() as Num
+    }
+
+    fn random(&self, origin: &Point3, u: (Num, Num)) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1., 0., 0.);
+        }
+        // reuse the first sample to pick the object, rescaled to [0,1)
+        let n = self.objects.len() as Num;
+        let index = Num::min(u.0 * n, n - 1.) as usize;
+        let u0 = u.0 * n - index as Num;
+        self.objects[index].random(origin, (u0, u.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Color, hittable::sphere::Sphere};
+
+    fn gray() -> Material {
+        Material::new_lambertian(Color::new(0.5, 0.5, 0.5))
+    }
+
+    // Horizontal plane y = 0, bounded to a large box
+    struct Floor(Material);
+
+    impl Hittable for Floor {
+        fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
+            let t = -r.origin().y / r.direction().y;
+            if !(t > ray_t.min && t < ray_t.max) {
+                return None;
+            }
+            let mut rec = HitRecord::new(r.at(t), t, &self.0);
+            rec.set_face_normal(r, &Vec3::new(0., 1., 0.));
+            Some(rec)
+        }
+
+        fn bounding_box(&self) -> Aabb {
+            Aabb::from_points(Point3::new(-1e3, -1e-3, -1e3), Point3::new(1e3, 1e-3, 1e3))
+        }
+    }
+
+    #[test]
+    fn hit_returns_the_closest_object() {
+        let mut world = HittableList::new()
+            .with(Sphere::new(Point3::new(0., 1., -5.), 1., gray()))
+            .with(Sphere::new(Point3::new(0., 1., -2.), 0.5, gray()));
+        world.add_custom(Floor(gray()));
+
+        let ray = Ray::new(Point3::new(0., 1., 0.), Vec3::new(0., 0., -1.));
+        let rec = world
+            .hit(&ray, Interval::new(0.001, Ray::INFINITY))
+            .unwrap();
+        assert!((rec.t - 1.5).abs() < 1e-6);
+
+        let down = Ray::new(Point3::new(3., 1., 0.), Vec3::new(0., -1., 0.));
+        let rec = world
+            .hit(&down, Interval::new(0.001, Ray::INFINITY))
+            .unwrap();
+        assert!((rec.t - 1.).abs() < 1e-6 && rec.front_face);
+    }
+
+    #[test]
+    fn packet_hits_match_single_ray_hits() {
+        let mut world: HittableList = (0..5)
+            .map(|k| Sphere::new(Point3::new(k as Num - 2., 0., -3. - k as Num), 0.7, gray()))
+            .collect();
+        world.add_custom(Floor(gray()));
+        let ray_t = Interval::new(0.001, Ray::INFINITY);
+
+        for batch in 0..8 {
+            let rays: [Ray; LANES] = std::array::from_fn(|lane| {
+                let k = (batch * LANES + lane) as Num;
+                Ray::new(
+                    Point3::new(0., 0.3, 0.),
+                    Vec3::new((k * 0.37).sin() * 0.8, (k * 0.53).cos() * 0.5, -1.),
+                )
+            });
+            let packet = world.hit_packet(&rays, ray_t);
+            for (ray, hit) in rays.iter().zip(packet.iter()) {
+                let single = world.hit(ray, ray_t);
+                assert_eq!(hit.is_some(), single.is_some());
+                if let (Some(a), Some(b)) = (hit, single) {
+                    assert_eq!(a.t, b.t);
+                    assert!(std::ptr::eq(a.mat, b.mat));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sampled_directions_hit_the_sphere() {
+        let sphere = Sphere::new(Point3::new(0., 0., -4.), 1., gray());
+        let origin = Point3::ZERO;
+        for k in 0..16 {
+            let u = (k as Num / 16., (k * 7 % 16) as Num / 16.);
+            let direction = sphere.random(&origin, u);
+            assert!(sphere.pdf_value(&origin, &direction) > 0.);
+        }
+        assert_eq!(sphere.pdf_value(&origin, &Vec3::new(0., 0., 1.)), 0.);
+    }
 }
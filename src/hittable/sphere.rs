@@ -1,21 +1,79 @@
-use super::{HitRecord, Hittable};
-use crate::{interval::Interval, material::Material, ray::Ray, vec3::*, Num};
+use std::sync::Arc;
+
+use super::{HitRecord, Hittable, Primitive};
+use crate::{
+    aabb::Aabb,
+    checkpoint::Fingerprint,
+    consts,
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    simd::{Floats, Mask, RayPacket, Vec3s},
+    vec3::*,
+    Num,
+};
 
 pub struct Sphere {
     center: Point3,
     radius: Num,
-    mat: Box<Material>,
+    mat: Arc<Material>,
 }
 
 impl Sphere {
-    pub fn new(c: Point3, r: Num, m: Box<Material>) -> Hittable {
-        Hittable::Sphere(Sphere {
+    #[allow(clippy::new_ret_no_self)]
+    // The material can be shared between spheres by passing an Arc
+    pub fn new<M: Into<Arc<Material>>>(c: Point3, r: Num, m: M) -> Primitive {
+        Primitive::Sphere(Sphere {
             center: c,
             radius: r,
-            mat: m,
+            mat: m.into(),
         })
     }
-    pub fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord> {
+
+    // Intersects all lanes of a packet at once. Returns the lanes hit within
+    // `(t_min, t_max)` and their distances, other lanes keep `t_max`.
+    #[inline(always)]
+    pub fn hit_packet(&self, r: &RayPacket, t_min: Floats, t_max: Floats) -> (Mask, Floats) {
+        let oc = r.origin - Vec3s::splat(&self.center);
+        let a = r.dir.lenght_sqr();
+        let half_b = oc.dot(&r.dir);
+        let c = oc.lenght_sqr() - Floats::splat(self.radius * self.radius);
+
+        let discriminant = half_b * half_b - a * c;
+        let valid = discriminant.ge(Floats::splat(0.));
+        if !valid.any() {
+            return (valid, t_max);
+        }
+        let sqrtd = discriminant.max(Floats::splat(0.)).sqrt();
+
+        // nearest root in range, same as `hit`
+        let near = (-half_b - sqrtd) / a;
+        let near_ok = near.gt(t_min) & near.lt(t_max);
+        let far = (-half_b + sqrtd) / a;
+        let far_ok = far.gt(t_min) & far.lt(t_max);
+
+        let hit = valid & (near_ok | far_ok);
+        let root = near_ok.select(near, far);
+        (hit, hit.select(root, t_max))
+    }
+
+    pub(crate) fn fingerprint(&self, f: &mut Fingerprint) {
+        f.vec(&self.center).num(self.radius);
+        self.mat.fingerprint(f);
+    }
+
+    // Hit record at distance `t` along `r`
+    pub(crate) fn record(&self, r: &Ray, t: Num) -> HitRecord<'_> {
+        let mut rec = HitRecord::new(r.at(t), t, &self.mat);
+        let outward_normal = (rec.p - self.center) / self.radius; // normalized by dividing by radius
+        rec.set_face_normal(r, &outward_normal);
+        rec
+    }
+}
+
+impl Hittable for Sphere {
+    #[inline(always)]
+    fn hit(&self, r: &Ray, ray_t: Interval) -> Option<HitRecord<'_>> {
         let oc = *r.origin() - self.center;
         let a = r.direction().lenght_sqr();
         let half_b = dot(&oc, r.direction());
@@ -37,10 +95,74 @@ impl Sphere {
         }
 
         // save hit record
-        let mut rec = HitRecord::new(r.at(root), root, self.mat.clone());
-        let outward_normal = (rec.p - self.center) / self.radius; // normalized by dividing by radius
-        rec.set_face_normal(r, &outward_normal);
+        Some(self.record(r, root))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let rvec = Vec3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - rvec, self.center + rvec)
+    }
+
+    // Uniform over the cone of directions the sphere covers seen from `origin`
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> Num {
+        if self
+            .hit(
+                &Ray::new(*origin, *direction),
+                Interval::new(0.001, Ray::INFINITY),
+            )
+            .is_none()
+        {
+            return 0.;
+        }
+        let distance_sqr = (self.center - *origin).lenght_sqr();
+        let cos_theta_max = (1. - self.radius * self.radius / distance_sqr)
+            .max(0.)
+            .sqrt();
+        let solid_angle = 2. * consts::PI * (1. - cos_theta_max);
+        1. / solid_angle
+    }
 
-        return Some(rec);
+    fn random(&self, origin: &Point3, u: (Num, Num)) -> Vec3 {
+        let direction = self.center - *origin;
+        let distance_sqr = direction.lenght_sqr();
+        let cos_theta_max = (1. - self.radius * self.radius / distance_sqr)
+            .max(0.)
+            .sqrt();
+
+        let z = 1. + u.1 * (cos_theta_max - 1.);
+        let phi = 2. * consts::PI * u.0;
+        let sin_theta = (1. - z * z).max(0.).sqrt();
+
+        // orthonormal basis around the direction to the center
+        let w = normalize(&direction);
+        let a = if w.x.abs() > 0.9 {
+            Vec3::new(0., 1., 0.)
+        } else {
+            Vec3::new(1., 0., 0.)
+        };
+        let v = normalize(&cross(&w, &a));
+        let u_axis = cross(&w, &v);
+        (sin_theta * phi.cos()) * u_axis + (sin_theta * phi.sin()) * v + z * w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn hit_record_borrows_the_shared_material() {
+        let mat = Arc::new(Material::new_lambertian(Color::new(0.5, 0.5, 0.5)));
+        let spheres = [
+            Sphere::new(Point3::new(0., 0., -2.), 0.5, mat.clone()),
+            Sphere::new(Point3::new(0., 0., 2.), 0.5, mat.clone()),
+        ];
+        for (sphere, z) in spheres.iter().zip([-1., 1.]) {
+            let r = Ray::new(Point3::ZERO, Vec3::new(0., 0., z));
+            let rec = sphere.hit(&r, Interval::new(0.001, Ray::INFINITY)).unwrap();
+            assert!(std::ptr::eq(rec.mat, &*mat));
+            assert!((rec.t - 1.5).abs() < 1e-6);
+        }
     }
 }
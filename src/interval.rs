@@ -1,17 +1,21 @@
 use crate::Num;
 
+#[derive(Clone, Copy)]
 pub struct Interval {
     pub min: Num,
     pub max: Num,
 }
 
-impl Interval {
-    pub fn default() -> Interval {
+impl Default for Interval {
+    fn default() -> Interval {
         Interval {
             min: Num::MIN,
             max: Num::MAX,
         }
     }
+}
+
+impl Interval {
     pub fn new(_min: Num, _max: Num) -> Interval {
         Interval {
             min: _min,
@@ -27,6 +31,13 @@ impl Interval {
     pub fn clamp(&self, x: Num) -> Num {
         Num::min(Num::max(x, self.min), self.max)
     }
+    // Smallest interval containing both
+    pub fn surrounding(a: &Interval, b: &Interval) -> Interval {
+        Interval {
+            min: Num::min(a.min, b.min),
+            max: Num::max(a.max, b.max),
+        }
+    }
 
     pub const EMPTY: Interval = Interval {
         min: Num::MAX,
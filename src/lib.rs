@@ -0,0 +1,41 @@
+pub mod aabb;
+pub mod camera;
+pub mod cancel;
+pub mod checkpoint;
+pub mod color;
+pub mod encode;
+pub mod framebuffer;
+pub mod hittable;
+pub mod interval;
+pub mod material;
+pub mod ray;
+pub mod sampler;
+pub mod simd;
+pub mod stats;
+pub mod vec3;
+
+pub use camera::{
+    Aperture, ApertureMask, Camera, CameraAnimation, CameraBuilder, Crop, FisheyeMapping,
+    FocusTarget, Interpolation, Keyframe, LensElement, LensSystem, Projection, Stereo,
+    StereoLayout,
+};
+pub use cancel::CancelToken;
+pub use color::Color;
+pub use framebuffer::Framebuffer;
+pub use hittable::{hittable_list::HittableList, sphere::Sphere, HitRecord, Hittable, Primitive};
+pub use material::{Material, Shader};
+pub use sampler::SamplerKind;
+pub use stats::{Progress, ProgressObserver, RenderStats};
+pub use vec3::{Point3, Vec3};
+
+// Scalar used by all the math types, the `f32` feature trades precision for
+// speed and memory on large scenes
+#[cfg(not(feature = "f32"))]
+pub type Num = f64;
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+
+#[cfg(feature = "f32")]
+pub type Num = f32;
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
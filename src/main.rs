@@ -1,24 +1,211 @@
-pub mod camera;
-pub mod color;
-pub mod hittable;
-pub mod interval;
-pub mod material;
-pub mod ray;
-pub mod vec3;
+use std::{env, path::Path, process, sync::Arc, time::Duration};
 
-use std::{fs, sync::Arc};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use raytracer::{
+    consts, encode, Camera, CameraAnimation, Color, Crop, FocusTarget, Framebuffer, HittableList,
+    Interpolation, Keyframe, Material, Num, Point3, Progress, ProgressObserver, RenderStats,
+    Sphere, Vec3,
+};
 
-use camera::Camera;
-use color::Color;
-use hittable::{hittable_list::HittableList, sphere::Sphere};
-use material::*;
-use rand::Rng;
-use vec3::*;
+const USAGE: &str = "usage: rust [--width N] [--samples N] [--seed N] \
+[--adaptive THRESHOLD] [--min-samples N] \
+[--output FILE.ppm|FILE.pfm] [--heatmap FILE.pgm] [--resume] [--frames N] [--percent N] \
+[--crop X0,Y0,X1,Y1] [--crop-px X,Y,W,H] [--full-frame]";
 
-type Num = f64;
+struct Args {
+    width: i32,
+    samples: i32,
+    adaptive: Num,    // Adaptive sampling threshold, 0 takes `samples` in every pixel
+    min_samples: i32, // Samples per pixel before adaptive sampling may stop
+    seed: u64,
+    output: String,
+    heatmap: Option<String>, // Where to write the per-pixel sample counts
+    resume: bool,
+    frames: usize, // Turntable frames to render, 0 for a still image
+    percent: Num,
+    crop: Option<Crop>, // Region to render, corners as fractions of the image or in pixels
+    full_frame: bool,   // Write a crop into an image of the full size, black elsewhere
+}
+
+impl Args {
+    fn parse() -> Result<Args, String> {
+        let mut args = Args {
+            width: 1200,
+            samples: 50,
+            adaptive: 0.,
+            min_samples: 16,
+            seed: 0,
+            output: String::from("./render.ppm"),
+            heatmap: None,
+            resume: false,
+            frames: 0,
+            percent: 100.,
+            crop: None,
+            full_frame: false,
+        };
+        let mut it = env::args().skip(1);
+        while let Some(arg) = it.next() {
+            let mut value = || it.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--width" => args.width = value()?.parse().map_err(|e| format!("{}", e))?,
+                "--samples" => args.samples = value()?.parse().map_err(|e| format!("{}", e))?,
+                "--adaptive" => args.adaptive = value()?.parse().map_err(|e| format!("{}", e))?,
+                "--min-samples" => {
+                    args.min_samples = value()?.parse().map_err(|e| format!("{}", e))?
+                }
+                "--seed" => args.seed = value()?.parse().map_err(|e| format!("{}", e))?,
+                "--output" => args.output = value()?,
+                "--heatmap" => args.heatmap = Some(value()?),
+                "--resume" => args.resume = true,
+                "--frames" => args.frames = value()?.parse().map_err(|e| format!("{}", e))?,
+                "--percent" => args.percent = value()?.parse().map_err(|e| format!("{}", e))?,
+                "--crop" => args.crop = Some(parse_crop(&value()?)?),
+                "--crop-px" => args.crop = Some(parse_crop_pixels(&value()?)?),
+                "--full-frame" => args.full_frame = true,
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        Ok(args)
+    }
+}
+
+fn parse_crop(value: &str) -> Result<Crop, String> {
+    let [x0, y0, x1, y1] = parse_values(value)?;
+    Ok(Crop::Normalized { x0, y0, x1, y1 })
+}
+
+fn parse_crop_pixels(value: &str) -> Result<Crop, String> {
+    let [x, y, width, height] = parse_values(value)?;
+    Ok(Crop::Pixels {
+        x,
+        y,
+        width,
+        height,
+    })
+}
+
+// Four comma separated values
+fn parse_values<T: std::str::FromStr>(value: &str) -> Result<[T; 4], String>
+where
+    T::Err: std::fmt::Display,
+{
+    let values = value
+        .split(',')
+        .map(|v| v.parse::<T>().map_err(|e| format!("{}", e)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = values.len();
+    values
+        .try_into()
+        .map_err(|_| format!("crop needs 4 values, found {}", count))
+}
 
 fn main() {
-    let mut rand = rand::thread_rng();
+    pretty_env_logger::init();
+
+    let args = Args::parse().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+    let world = random_scene(args.seed);
+
+    let mut cam = Camera::builder()
+        .aspect_ratio(16.0 as Num / 9.0)
+        .image_width(args.width)
+        .resolution_percent(args.percent)
+        .samples_per_pixel(args.samples)
+        .adaptive_threshold(args.adaptive)
+        .min_samples_per_pixel(args.min_samples)
+        .max_depth(50)
+        .seed(args.seed)
+        .vfov(consts::PI / 9.0)
+        .lookfrom(Point3::new(13., 2., 3.))
+        .lookat(Point3::new(0., 0., 0.))
+        .vup(Vec3::new(0., 1., 0.))
+        .defocus_angle(consts::PI / 300.)
+        .pass_samples(5)
+        .snapshot_interval(Duration::from_secs(10))
+        .progress(Arc::new(ConsoleProgress))
+        .checkpoint_path(format!("{}.ckpt", args.output))
+        .resume(args.resume);
+    if let Some(crop) = args.crop {
+        cam = cam.crop(crop);
+    }
+    let mut cam = cam.build();
+    cam.focus_on(&world, FocusTarget::Lookat);
+
+    // First Ctrl-C stops rendering and keeps the image, a second one exits
+    let cancel = cam.cancel_token.clone();
+    ctrlc::set_handler(move || {
+        if cancel.is_cancelled() {
+            process::exit(130);
+        }
+        cancel.cancel();
+    })
+    .unwrap();
+
+    let output = Path::new(&args.output);
+    let heatmap = args.heatmap.as_ref().map(Path::new);
+    if args.frames > 0 {
+        let animation = turntable(&cam);
+        cam.render_sequence(
+            Arc::new(world),
+            &animation,
+            args.frames,
+            |n, frame, finished| {
+                save_image(frame, &encode::frame_path(output, n));
+                if let Some(heatmap) = heatmap.filter(|_| finished) {
+                    save_heatmap(frame, args.samples, &encode::frame_path(heatmap, n));
+                }
+            },
+        );
+        return;
+    }
+
+    // Crops rendered with --full-frame line up when layered on each other,
+    // without snapshots or checkpoints
+    if args.full_frame {
+        let (width, height) = cam.image_size();
+        let mut image = Framebuffer::new(width, height);
+        cam.render_into(Arc::new(world), &mut image);
+        save_image(&image, output);
+        if let Some(heatmap) = heatmap {
+            save_heatmap(&image, args.samples, heatmap);
+        }
+        return;
+    }
+
+    let image = cam.render_progressive(Arc::new(world), |snapshot| save_image(snapshot, output));
+    save_image(&image, output);
+    if let Some(heatmap) = heatmap {
+        save_heatmap(&image, args.samples, heatmap);
+    }
+}
+
+// One turn around `lookat`, keeping the height of the camera. The animation
+// loops, so the sequence can be played over and over without a stutter.
+fn turntable(cam: &Camera) -> CameraAnimation {
+    const KEYS: usize = 8;
+    let offset = cam.lookfrom - cam.lookat;
+    let radius = (offset.x * offset.x + offset.z * offset.z).sqrt();
+    let start = offset.z.atan2(offset.x);
+    let mut animation = CameraAnimation::new(Interpolation::Spline);
+    animation.looping = true;
+    for k in 0..=KEYS {
+        let time = k as Num / KEYS as Num;
+        let angle = start + 2. * consts::PI * time;
+        let lookfrom = cam.lookat + Vec3::new(radius * angle.cos(), offset.y, radius * angle.sin());
+        animation.add(Keyframe {
+            lookfrom,
+            ..Keyframe::from_camera(time, cam)
+        });
+    }
+    animation
+}
+
+fn random_scene(seed: u64) -> HittableList {
+    // Seeded so the scene is identical between runs
+    let mut rand = Pcg32::seed_from_u64(seed);
     // World
     let mut world = HittableList::new();
     let groud_material = Box::new(Material::new_lambertian(Color::new(0.5, 0.5, 0.5)));
@@ -40,12 +227,17 @@ fn main() {
             if (center - Point3::new(4., 0.2, 0.)).lenght() > 0.9 {
                 if choose_mat < 0.8 {
                     // diffuse
-                    let albedo = Color::random() * &Color::random();
+                    let albedo = Color::new(rand.gen(), rand.gen(), rand.gen())
+                        * &Color::new(rand.gen(), rand.gen(), rand.gen());
                     let sphere_material = Box::new(Material::new_lambertian(albedo));
                     world.add(Sphere::new(center, 0.2, sphere_material));
                 } else if choose_mat < 0.95 {
                     // metal
-                    let albedo = Color::random_range(0.5, 1.);
+                    let albedo = Color::new(
+                        rand.gen_range(0.5..1.),
+                        rand.gen_range(0.5..1.),
+                        rand.gen_range(0.5..1.),
+                    );
                     let fuzz = rand.gen::<Num>();
                     let sphere_material = Box::new(Material::new_metal(albedo, fuzz));
                     world.add(Sphere::new(center, 0.2, sphere_material));
@@ -67,22 +259,34 @@ fn main() {
     let material3 = Box::new(Material::new_metal(Color::new(0.7, 0.6, 0.5), 0.0));
     world.add(Sphere::new(Vec3::new(4., 1., 0.), 1.0, material3));
 
-    let mut cam = Camera::default();
-    cam.aspect_ratio = 16.0 as Num / 9.0;
-    cam.image_width = 1200;
-    cam.samples_per_pixel = 50;
-    cam.max_depth = 50;
+    world
+}
+
+struct ConsoleProgress;
+
+impl ProgressObserver for ConsoleProgress {
+    fn on_progress(&self, progress: &Progress) {
+        eprint!(
+            "\r{:5.1}% {:.0} samples/s, ETA {:.0?}    ",
+            progress.fraction * 100.,
+            progress.samples_per_sec,
+            progress.eta.unwrap_or_default()
+        );
+    }
 
-    cam.vfov = std::f64::consts::PI / 9.0;
-    cam.lookfrom = Point3::new(13., 2., 3.);
-    cam.lookat = Point3::new(0., 0., 0.);
-    cam.vup = Vec3::new(0., 1., 0.);
+    fn on_finish(&self, stats: &RenderStats) {
+        eprintln!("\n{}", stats);
+    }
+}
 
-    cam.defocus_angle = std::f64::consts::PI / 300.;
-    cam.focus_dist = 10.0;
+fn save_image(image: &Framebuffer, path: &Path) {
+    if let Err(e) = encode::save(image, path) {
+        eprintln!("cannot write {}: {}", path.display(), e);
+    }
+}
 
-    let mut f = fs::File::create("./render.ppm").unwrap();
-    cam.initialize();
-    cam.render(&mut f, Arc::new(world));
-    drop(f);
+fn save_heatmap(image: &Framebuffer, max_samples: i32, path: &Path) {
+    if let Err(e) = encode::save_heatmap(image, max_samples, path) {
+        eprintln!("cannot write {}: {}", path.display(), e);
+    }
 }
@@ -1,12 +1,48 @@
-use rand::Rng;
+use std::sync::Arc;
 
-use crate::{color::Color, hittable::HitRecord, ray::Ray, vec3::*, Num};
+use crate::{
+    checkpoint::Fingerprint, color::Color, consts, hittable::HitRecord, ray::Ray, sampler::Sampler,
+    vec3::*, Num,
+};
+
+// Surface scattering model. Implement it for custom materials and use them
+// through `Material::Custom`.
+pub trait Shader: Send + Sync {
+    // Sample a scattered ray, returns its weight (`eval / pdf`) and the ray.
+    // None absorbs the path.
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)>;
+
+    // BSDF times the cosine term for a given scattered ray,
+    // zero for perfectly specular materials
+    fn eval(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        Color::ZERO
+    }
+
+    // Density, per solid angle, of `scatter` returning `scattered`
+    fn pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Num {
+        0.
+    }
+
+    // Light emitted towards the incoming ray
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::ZERO
+    }
+}
 
 #[derive(Clone)]
 pub enum Material {
     Lambertian { albedo: Color },
     Metal { albedo: Color, fuzz: Num },
     Dielectric { ir: Num },
+    // Random-walk subsurface scattering inside a closed surface,
+    // `mfp` is the mean free path per color channel
+    Subsurface { albedo: Color, mfp: Color, ir: Num },
+    Custom(Arc<dyn Shader>),
 }
 
 impl Material {
@@ -22,17 +58,68 @@ impl Material {
         Self::Dielectric { ir }
     }
 
+    // Panics unless the mean free path is positive in every channel
+    pub fn new_subsurface(albedo: Color, mfp: Color, ir: Num) -> Self {
+        assert!(
+            mfp.x > 0. && mfp.y > 0. && mfp.z > 0.,
+            "mean free path must be positive, got {}",
+            mfp
+        );
+        Self::Subsurface { albedo, mfp, ir }
+    }
+
+    pub fn custom<S: Shader + 'static>(shader: S) -> Self {
+        Self::Custom(Arc::new(shader))
+    }
+
+    // Custom shaders cannot be looked into, only their kind is hashed
+    pub(crate) fn fingerprint(&self, f: &mut Fingerprint) {
+        match self {
+            Self::Lambertian { albedo } => f.int(0).vec(albedo),
+            Self::Metal { albedo, fuzz } => f.int(1).vec(albedo).num(*fuzz),
+            Self::Dielectric { ir } => f.int(2).num(*ir),
+            Self::Subsurface { albedo, mfp, ir } => f.int(3).vec(albedo).vec(mfp).num(*ir),
+            Self::Custom(_) => f.int(4),
+        };
+    }
+
     fn reflectance(cosine: Num, ref_idx: Num) -> Num {
         let mut r0 = (1. - ref_idx) / (1. + ref_idx);
         r0 = r0 * r0;
         r0 + (1. - r0) * Num::powi(1. - cosine, 5)
     }
 
+    fn refract_or_reflect(ir: Num, r_in: &Ray, rec: &HitRecord, sampler: &mut dyn Sampler) -> Ray {
+        let refraction_ratio = if rec.front_face { 1. / ir } else { ir };
+
+        let unit_direction = normalize(r_in.direction());
+        let cos_theta = Num::min(dot(&-unit_direction, &rec.normal), 1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let cannot_refract = refraction_ratio * sin_theta > 1.0;
+        let direction = if cannot_refract
+            || Self::reflectance(cos_theta, refraction_ratio) > sampler.get_1d()
+        {
+            reflect(&unit_direction, &rec.normal)
+        } else {
+            refract(&unit_direction, &rec.normal, refraction_ratio)
+        };
+
+        Ray::new(rec.p, direction)
+    }
+}
+
+impl Shader for Material {
     // Return attenuation Color, scattered Ray
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        match *self {
-            Self::Lambertian { albedo } => {
-                let mut scatter_direction = rec.normal + Vec3::random_unit_sphere();
+    fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<(Color, Ray)> {
+        match self {
+            &Self::Lambertian { albedo } => {
+                let mut scatter_direction = rec.normal + Vec3::sample_unit_sphere(sampler.get_2d());
 
                 // Catch degenerate scatter direction
                 if scatter_direction.near_zero() {
@@ -41,34 +128,118 @@ impl Material {
 
                 Some((albedo, Ray::new(rec.p, scatter_direction)))
             }
-            Self::Metal { albedo, fuzz } => {
-                let reflected = reflect(&normalize(&r_in.direction()), &rec.normal);
-                let scattered = Ray::new(rec.p, reflected + fuzz * Vec3::random_unit_sphere());
+            &Self::Metal { albedo, fuzz } => {
+                let reflected = reflect(&normalize(r_in.direction()), &rec.normal);
+                let scattered = Ray::new(
+                    rec.p,
+                    reflected + fuzz * Vec3::sample_unit_sphere(sampler.get_2d()),
+                );
                 match dot(scattered.direction(), &rec.normal) > 0. {
                     false => None,
                     true => Some((albedo, scattered)),
                 }
             }
-            Self::Dielectric { ir } => {
-                let mut rand = rand::thread_rng();
-
-                let refraction_ratio = if rec.front_face { 1. / ir } else { ir };
-
-                let unit_direction = normalize(r_in.direction());
-                let cos_theta = Num::min(dot(&-unit_direction, &rec.normal), 1.0);
-                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-
-                let cannot_refract = refraction_ratio * sin_theta > 1.0;
-                let direction = if cannot_refract
-                    || Self::reflectance(cos_theta, refraction_ratio) > rand.gen::<Num>()
-                {
-                    reflect(&unit_direction, &rec.normal)
-                } else {
-                    refract(&unit_direction, &rec.normal, refraction_ratio)
-                };
-
-                let scattered = Ray::new(rec.p, direction);
-                Some((Color::new(1., 1., 1.), scattered))
+            &Self::Dielectric { ir } => Some((
+                Color::new(1., 1., 1.),
+                Material::refract_or_reflect(ir, r_in, rec, sampler),
+            )),
+            // Only the boundary is handled here, the walk inside is traced by the camera
+            &Self::Subsurface { ir, .. } => Some((
+                Color::new(1., 1., 1.),
+                Material::refract_or_reflect(ir, r_in, rec, sampler),
+            )),
+            Self::Custom(shader) => shader.scatter(r_in, rec, sampler),
+        }
+    }
+
+    fn eval(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        match self {
+            &Self::Lambertian { albedo } => {
+                let cosine = dot(&rec.normal, &normalize(scattered.direction())).max(0.);
+                albedo * cosine / consts::PI
+            }
+            Self::Custom(shader) => shader.eval(r_in, rec, scattered),
+            _ => Color::ZERO,
+        }
+    }
+
+    fn pdf(&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Num {
+        match self {
+            Self::Lambertian { .. } => {
+                let cosine = dot(&rec.normal, &normalize(scattered.direction()));
+                cosine.max(0.) / consts::PI
+            }
+            Self::Custom(shader) => shader.pdf(r_in, rec, scattered),
+            _ => 0.,
+        }
+    }
+
+    fn emitted(&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        match self {
+            Self::Custom(shader) => shader.emitted(r_in, rec),
+            _ => Color::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    struct Glow(Color);
+
+    impl Shader for Glow {
+        fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut dyn Sampler) -> Option<(Color, Ray)> {
+            None
+        }
+
+        fn pdf(&self, _: &Ray, _: &HitRecord, _: &Ray) -> Num {
+            2.
+        }
+
+        fn emitted(&self, _: &Ray, _: &HitRecord) -> Color {
+            self.0
+        }
+    }
+
+    #[test]
+    fn custom_shaders_are_dispatched() {
+        let mat = Material::custom(Glow(Color::new(1., 2., 3.)));
+        let r = Ray::new(Point3::ZERO, Vec3::new(0., 0., -1.));
+        let mut rec = HitRecord::new(Point3::new(0., 0., -1.), 1., &mat);
+        rec.set_face_normal(&r, &Vec3::new(0., 0., 1.));
+        let mut sampler = SamplerKind::Independent.create(1, 0);
+        sampler.start_pixel_sample(0, 0, 0);
+
+        assert!(mat.scatter(&r, &rec, &mut *sampler).is_none());
+        assert_eq!(mat.emitted(&r, &rec).z, 3.);
+        assert_eq!(mat.pdf(&r, &rec, &r), 2.);
+        assert_eq!(mat.eval(&r, &rec, &r).x, 0.);
+    }
+
+    #[test]
+    #[should_panic(expected = "mean free path")]
+    fn subsurface_rejects_an_empty_mean_free_path() {
+        Material::new_subsurface(Color::new(1., 1., 1.), Color::new(0.1, 0., 0.1), 1.3);
+    }
+
+    #[test]
+    fn lambertian_scatters_above_the_surface() {
+        let mat = Material::new_lambertian(Color::new(0.5, 0.5, 0.5));
+        let r = Ray::new(Point3::ZERO, Vec3::new(0., 0., -1.));
+        let mut rec = HitRecord::new(Point3::new(0., 0., -1.), 1., &mat);
+        rec.set_face_normal(&r, &Vec3::new(0., 0., 1.));
+        let mut sampler = SamplerKind::Sobol.create(64, 0);
+        for index in 0..64 {
+            sampler.start_pixel_sample(0, 0, index);
+            let (weight, scattered) = mat.scatter(&r, &rec, &mut *sampler).unwrap();
+            assert!(dot(scattered.direction(), &rec.normal) >= 0.);
+            // the weight is eval over pdf, the cosine cancels
+            let pdf = mat.pdf(&r, &rec, &scattered);
+            if pdf > 1e-3 {
+                let ratio = mat.eval(&r, &rec, &scattered) / pdf;
+                assert!((ratio.x - weight.x).abs() < 1e-4);
             }
         }
     }
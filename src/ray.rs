@@ -3,6 +3,7 @@ use crate::{
     Num,
 };
 
+#[derive(Clone, Copy)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
@@ -0,0 +1,174 @@
+use rand_pcg::Pcg32;
+
+use crate::Num;
+
+use self::{
+    halton::HaltonSampler, independent::IndependentSampler, sobol::SobolSampler,
+    stratified::StratifiedSampler,
+};
+
+pub mod halton;
+pub mod independent;
+pub mod sobol;
+pub mod stratified;
+
+// Source of the sample values used for a single camera path.
+// Every path starts with `start_pixel_sample`, after which each call to
+// `get_1d`/`get_2d` consumes the next dimension(s) of the sample.
+pub trait Sampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32);
+    fn get_1d(&mut self) -> Num;
+    fn get_2d(&mut self) -> (Num, Num) {
+        (self.get_1d(), self.get_1d())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SamplerKind {
+    Independent,
+    Stratified,
+    Halton,
+    Sobol,
+}
+
+impl SamplerKind {
+    // All samplers are deterministic functions of the seed, pixel and sample index
+    pub fn create(&self, samples_per_pixel: i32, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            Self::Independent => Box::new(IndependentSampler::new(seed)),
+            Self::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            Self::Halton => Box::new(HaltonSampler::new(seed)),
+            Self::Sobol => Box::new(SobolSampler::new(seed)),
+        }
+    }
+}
+
+// Random stream of a single pixel sample, independent of the order in
+// which pixels and samples are rendered
+pub(crate) fn sample_rng(seed: u64, i: i32, j: i32, sample_index: i32) -> Pcg32 {
+    Pcg32::new(
+        hash(&[seed, i as u64, j as u64, sample_index as u64]),
+        0xa02bdbf7bb3c0a7,
+    )
+}
+
+// Largest Num strictly below 1, used to keep samples in [0,1)
+pub const ONE_MINUS_EPSILON: Num = 1. - Num::EPSILON / 2.;
+
+// Convert the bits of a 32 bit fixed point fraction to a sample in [0,1)
+#[inline(always)]
+pub(crate) fn u32_to_unit(x: u32) -> Num {
+    Num::min(x as Num * (1. / 4294967296.), ONE_MINUS_EPSILON)
+}
+
+// 64 bit finalizer from MurmurHash3, a cheap good quality bit mixer
+#[inline(always)]
+pub(crate) fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+#[inline(always)]
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0x9e3779b97f4a7c15, |h, &v| {
+        mix_bits(h ^ v.wrapping_add(0x9e3779b97f4a7c15))
+    })
+}
+
+// Element `i` of a random permutation of 0..l selected by `p` (Kensler 2013)
+pub(crate) fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    #[test]
+    fn samples_are_in_unit_interval() {
+        for kind in KINDS {
+            let mut sampler = kind.create(16, 7);
+            for index in 0..64 {
+                sampler.start_pixel_sample(3, -2, index);
+                // more dimensions than the Halton prime table has
+                for _ in 0..40 {
+                    let x = sampler.get_1d();
+                    assert!((0. ..1.).contains(&x), "{:?} gave {}", kind, x);
+                    let (u, v) = sampler.get_2d();
+                    assert!((0. ..1.).contains(&u) && (0. ..1.).contains(&v));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_are_deterministic() {
+        for kind in KINDS {
+            let mut a = kind.create(8, 42);
+            let mut b = kind.create(8, 42);
+            // different order of pixels and samples
+            b.start_pixel_sample(9, 9, 5);
+            b.get_2d();
+            a.start_pixel_sample(1, 2, 3);
+            b.start_pixel_sample(1, 2, 3);
+            for _ in 0..10 {
+                assert_eq!(a.get_1d(), b.get_1d(), "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn permutation_element_is_a_permutation() {
+        for l in [1, 2, 7, 16, 33] {
+            let mut seen: Vec<u32> = (0..l).map(|i| permutation_element(i, l, 0xdead)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..l).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn u32_to_unit_stays_below_one() {
+        assert_eq!(u32_to_unit(0), 0.);
+        assert!(u32_to_unit(u32::MAX) < 1.);
+    }
+}
@@ -0,0 +1,98 @@
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+use super::{hash, sample_rng, u32_to_unit, Sampler, ONE_MINUS_EPSILON};
+use crate::Num;
+
+const PRIMES: [u32; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Halton sequence with a per-pixel Cranley-Patterson rotation.
+// Dimensions past the prime table fall back to uniform random numbers.
+pub struct HaltonSampler {
+    pixel: (i32, i32),
+    sample_index: u64,
+    dimension: usize,
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> HaltonSampler {
+        HaltonSampler {
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+            seed,
+            rng: sample_rng(seed, 0, 0, 0),
+        }
+    }
+}
+
+pub(crate) fn radical_inverse(base: u32, mut a: u64) -> Num {
+    let inv_base = 1. / base as Num;
+    let mut inv_base_m = 1. as Num;
+    let mut reversed_digits = 0u64;
+    while a > 0 {
+        let next = a / base as u64;
+        let digit = a - next * base as u64;
+        reversed_digits = reversed_digits * base as u64 + digit;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    Num::min(reversed_digits as Num * inv_base_m, ONE_MINUS_EPSILON)
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32) {
+        self.pixel = (i, j);
+        self.sample_index = sample_index as u64;
+        self.dimension = 0;
+        self.rng = sample_rng(self.seed, i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> Num {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension >= PRIMES.len() {
+            return self.rng.gen::<Num>();
+        }
+
+        let offset = u32_to_unit(hash(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            dimension as u64,
+        ]) as u32);
+        let x = radical_inverse(PRIMES[dimension], self.sample_index) + offset;
+        if x >= 1. {
+            x - 1.
+        } else {
+            x
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn radical_inverse_mirrors_digits() {
+        assert_eq!(radical_inverse(2, 0), 0.);
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 6), 0.375);
+        assert!((radical_inverse(3, 5) - 7. / 9.).abs() < 1e-6);
+    }
+
+    #[test]
+    fn seed_changes_the_rotation() {
+        let mut a = HaltonSampler::new(1);
+        let mut b = HaltonSampler::new(2);
+        a.start_pixel_sample(10, 20, 0);
+        b.start_pixel_sample(10, 20, 0);
+        assert_ne!(a.get_2d(), b.get_2d());
+    }
+}
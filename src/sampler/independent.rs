@@ -0,0 +1,30 @@
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+use super::{sample_rng, Sampler};
+use crate::Num;
+
+// Plain uniform random samples, no correlation between dimensions
+pub struct IndependentSampler {
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> IndependentSampler {
+        IndependentSampler {
+            seed,
+            rng: sample_rng(seed, 0, 0, 0),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32) {
+        self.rng = sample_rng(self.seed, i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> Num {
+        self.rng.gen::<Num>()
+    }
+}
@@ -0,0 +1,145 @@
+use super::{hash, u32_to_unit, Sampler};
+use crate::Num;
+
+// Generator matrices of the first four Sobol dimensions
+const SOBOL_MATRICES: [[u32; 32]; 4] = [
+    sobol_matrix(0, 0, &[]),
+    sobol_matrix(1, 0, &[1]),
+    sobol_matrix(2, 1, &[1, 3]),
+    sobol_matrix(3, 1, &[1, 3, 1]),
+];
+
+// Direction numbers from primitive polynomial of degree `s` with
+// coefficients `a` and initial values `m` (Joe & Kuo); degree 0 is the
+// van der Corput sequence.
+const fn sobol_matrix(s: usize, a: u32, m: &[u32]) -> [u32; 32] {
+    let mut v = [0u32; 32];
+    if s == 0 {
+        let mut k = 0;
+        while k < 32 {
+            v[k] = 1 << (31 - k);
+            k += 1;
+        }
+        return v;
+    }
+
+    let mut mk = [0u32; 32];
+    let mut k = 0;
+    while k < 32 {
+        if k < s {
+            mk[k] = m[k];
+        } else {
+            let mut x = mk[k - s] ^ (mk[k - s] << s);
+            let mut i = 1;
+            while i < s {
+                if (a >> (s - 1 - i)) & 1 == 1 {
+                    x ^= mk[k - i] << i;
+                }
+                i += 1;
+            }
+            mk[k] = x;
+        }
+        v[k] = mk[k] << (31 - k);
+        k += 1;
+    }
+    v
+}
+
+fn sobol(mut index: u32, dimension: usize) -> u32 {
+    let mut x = 0;
+    let mut bit = 0;
+    while index != 0 {
+        if index & 1 == 1 {
+            x ^= SOBOL_MATRICES[dimension][bit];
+        }
+        index >>= 1;
+        bit += 1;
+    }
+    x
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+// Owen-scrambled Sobol points (Burley 2020). Dimensions are used in
+// groups of four, each group with its own shuffled index, which keeps the
+// sequence well distributed for any number of dimensions.
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    sample_index: u32,
+    dimension: u32,
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> SobolSampler {
+        SobolSampler {
+            seed,
+            pixel_seed: 0,
+            sample_index: 0,
+            dimension: 0,
+        }
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32) {
+        self.pixel_seed = hash(&[self.seed, i as u64, j as u64]);
+        self.sample_index = sample_index as u32;
+        self.dimension = 0;
+    }
+
+    fn get_1d(&mut self) -> Num {
+        let group = self.dimension / 4;
+        let component = (self.dimension % 4) as usize;
+        self.dimension += 1;
+
+        let seed = hash(&[self.pixel_seed, group as u64]);
+        let index = nested_uniform_scramble(self.sample_index, seed as u32);
+        let x = sobol(index, component);
+        u32_to_unit(nested_uniform_scramble(
+            x,
+            hash(&[seed, component as u64]) as u32,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unscrambled_points_match_the_sequence() {
+        let points: Vec<_> = (0..4)
+            .map(|i| {
+                (
+                    sobol(i, 0) as f64 / 4294967296.,
+                    sobol(i, 1) as f64 / 4294967296.,
+                )
+            })
+            .collect();
+        assert_eq!(points, [(0., 0.), (0.5, 0.5), (0.25, 0.75), (0.75, 0.25)]);
+    }
+
+    #[test]
+    fn scrambled_points_are_stratified() {
+        // every power of two prefix has one point in each elementary interval
+        let mut sampler = SobolSampler::new(3);
+        let mut strata = [0; 8];
+        for index in 0..8 {
+            sampler.start_pixel_sample(0, 0, index);
+            strata[(sampler.get_1d() * 8.) as usize] += 1;
+        }
+        assert_eq!(strata, [1; 8]);
+    }
+}
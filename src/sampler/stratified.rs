@@ -0,0 +1,99 @@
+use rand::Rng;
+use rand_pcg::Pcg32;
+
+use super::{hash, permutation_element, sample_rng, Sampler, ONE_MINUS_EPSILON};
+use crate::Num;
+
+// Jittered samples, every dimension of a pixel is split into
+// `samples_per_pixel` strata (a grid for 2D) visited in a shuffled order
+pub struct StratifiedSampler {
+    samples_per_pixel: u32,
+    x_strata: u32,
+    y_strata: u32,
+    pixel: (i32, i32),
+    sample_index: u32,
+    dimension: u32,
+    seed: u64,
+    rng: Pcg32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: i32, seed: u64) -> StratifiedSampler {
+        let samples_per_pixel = samples_per_pixel.max(1) as u32;
+        let x_strata = ((samples_per_pixel as Num).sqrt() as u32).max(1);
+        let y_strata = samples_per_pixel.div_ceil(x_strata);
+        StratifiedSampler {
+            samples_per_pixel,
+            x_strata,
+            y_strata,
+            pixel: (0, 0),
+            sample_index: 0,
+            dimension: 0,
+            seed,
+            rng: sample_rng(seed, 0, 0, 0),
+        }
+    }
+
+    fn stratum(&self, count: u32) -> u32 {
+        let seed = hash(&[
+            self.seed,
+            self.pixel.0 as u64,
+            self.pixel.1 as u64,
+            self.dimension as u64,
+        ]);
+        let index = self.sample_index % count;
+        permutation_element(index, count, seed as u32)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, i: i32, j: i32, sample_index: i32) {
+        self.pixel = (i, j);
+        self.sample_index = sample_index as u32;
+        self.dimension = 0;
+        self.rng = sample_rng(self.seed, i, j, sample_index);
+    }
+
+    fn get_1d(&mut self) -> Num {
+        let stratum = self.stratum(self.samples_per_pixel);
+        self.dimension += 1;
+        let jitter = self.rng.gen::<Num>();
+        Num::min(
+            (stratum as Num + jitter) / self.samples_per_pixel as Num,
+            ONE_MINUS_EPSILON,
+        )
+    }
+
+    fn get_2d(&mut self) -> (Num, Num) {
+        let stratum = self.stratum(self.x_strata * self.y_strata);
+        self.dimension += 2;
+        let (x, y) = (stratum % self.x_strata, stratum / self.x_strata);
+        let (dx, dy) = (self.rng.gen::<Num>(), self.rng.gen::<Num>());
+        (
+            Num::min((x as Num + dx) / self.x_strata as Num, ONE_MINUS_EPSILON),
+            Num::min((y as Num + dy) / self.y_strata as Num, ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_stratum_is_visited_once() {
+        let n = 16;
+        let mut sampler = StratifiedSampler::new(n, 1);
+        let mut strata_1d = vec![0; n as usize];
+        let mut strata_2d = vec![0; n as usize];
+        for index in 0..n {
+            sampler.start_pixel_sample(4, 5, index);
+            let x = sampler.get_1d();
+            strata_1d[(x * n as Num) as usize] += 1;
+            let (u, v) = sampler.get_2d();
+            strata_2d[(v * 4.) as usize * 4 + (u * 4.) as usize] += 1;
+        }
+        assert!(strata_1d.iter().all(|&c| c == 1));
+        assert!(strata_2d.iter().all(|&c| c == 1));
+    }
+}
@@ -0,0 +1,123 @@
+use std::ops;
+
+use crate::{ray::Ray, vec3::Vec3};
+
+// Lane-parallel math for tracing several rays at once. A `Floats` holds one
+// value per lane, 4 f64 or 8 f32, which fills a 256-bit AVX register.
+// With AVX enabled at compile time (e.g. `-C target-cpu=native`) it maps to
+// `std::arch` intrinsics, otherwise to plain arrays.
+#[cfg(not(feature = "f32"))]
+pub const LANES: usize = 4;
+#[cfg(feature = "f32")]
+pub const LANES: usize = 8;
+
+// Whether `Floats` uses AVX. The plain array fallback is slower than tracing
+// rays one at a time, so packets are only worth it when this is set.
+pub const VECTORIZED: bool = cfg!(all(target_arch = "x86_64", target_feature = "avx"));
+
+#[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+mod avx;
+#[cfg(all(target_arch = "x86_64", target_feature = "avx"))]
+pub use self::avx::{Floats, Mask};
+
+#[cfg(not(all(target_arch = "x86_64", target_feature = "avx")))]
+mod scalar;
+#[cfg(not(all(target_arch = "x86_64", target_feature = "avx")))]
+pub use self::scalar::{Floats, Mask};
+
+// One `Vec3` per lane, stored as a structure of arrays
+#[derive(Clone, Copy)]
+pub struct Vec3s {
+    pub x: Floats,
+    pub y: Floats,
+    pub z: Floats,
+}
+
+impl Vec3s {
+    pub fn splat(v: &Vec3) -> Vec3s {
+        Vec3s {
+            x: Floats::splat(v.x),
+            y: Floats::splat(v.y),
+            z: Floats::splat(v.z),
+        }
+    }
+
+    pub fn from_lanes(v: [Vec3; LANES]) -> Vec3s {
+        Vec3s {
+            x: Floats::from_array(v.map(|v| v.x)),
+            y: Floats::from_array(v.map(|v| v.y)),
+            z: Floats::from_array(v.map(|v| v.z)),
+        }
+    }
+
+    pub fn lane(&self, i: usize) -> Vec3 {
+        Vec3::new(
+            self.x.to_array()[i],
+            self.y.to_array()[i],
+            self.z.to_array()[i],
+        )
+    }
+
+    pub fn dot(&self, u: &Vec3s) -> Floats {
+        self.x * u.x + self.y * u.y + self.z * u.z
+    }
+
+    pub fn lenght_sqr(&self) -> Floats {
+        self.dot(self)
+    }
+}
+
+impl ops::Add for Vec3s {
+    type Output = Self;
+    fn add(self, v: Self) -> Self::Output {
+        Vec3s {
+            x: self.x + v.x,
+            y: self.y + v.y,
+            z: self.z + v.z,
+        }
+    }
+}
+impl ops::Sub for Vec3s {
+    type Output = Self;
+    fn sub(self, v: Self) -> Self::Output {
+        Vec3s {
+            x: self.x - v.x,
+            y: self.y - v.y,
+            z: self.z - v.z,
+        }
+    }
+}
+impl ops::Mul<Floats> for Vec3s {
+    type Output = Self;
+    fn mul(self, t: Floats) -> Self::Output {
+        Vec3s {
+            x: self.x * t,
+            y: self.y * t,
+            z: self.z * t,
+        }
+    }
+}
+
+// `LANES` rays traced together, with the reciprocal direction precomputed for
+// the box slab tests
+pub struct RayPacket {
+    pub origin: Vec3s,
+    pub dir: Vec3s,
+    pub inv_dir: Vec3s,
+}
+
+impl RayPacket {
+    pub fn new(rays: &[Ray; LANES]) -> RayPacket {
+        let dir = Vec3s::from_lanes(rays.each_ref().map(|r| *r.direction()));
+        let one = Floats::splat(1.);
+        RayPacket {
+            origin: Vec3s::from_lanes(rays.each_ref().map(|r| *r.origin())),
+            dir,
+            inv_dir: Vec3s {
+                x: one / dir.x,
+                y: one / dir.y,
+                z: one / dir.z,
+            },
+        }
+    }
+}
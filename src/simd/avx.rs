@@ -0,0 +1,166 @@
+use std::{arch::x86_64::*, mem, ops};
+
+use super::LANES;
+use crate::Num;
+
+// The intrinsics for the selected scalar, under common names
+#[cfg(not(feature = "f32"))]
+mod reg {
+    pub use std::arch::x86_64::{
+        __m256d as Reg, _mm256_add_pd as add, _mm256_and_pd as and, _mm256_blendv_pd as blendv,
+        _mm256_cmp_pd as cmp, _mm256_div_pd as div, _mm256_max_pd as max, _mm256_min_pd as min,
+        _mm256_movemask_pd as movemask, _mm256_mul_pd as mul, _mm256_or_pd as or,
+        _mm256_set1_pd as set1, _mm256_sqrt_pd as sqrt, _mm256_sub_pd as sub, _mm256_xor_pd as xor,
+    };
+}
+#[cfg(feature = "f32")]
+mod reg {
+    pub use std::arch::x86_64::{
+        __m256 as Reg, _mm256_add_ps as add, _mm256_and_ps as and, _mm256_blendv_ps as blendv,
+        _mm256_cmp_ps as cmp, _mm256_div_ps as div, _mm256_max_ps as max, _mm256_min_ps as min,
+        _mm256_movemask_ps as movemask, _mm256_mul_ps as mul, _mm256_or_ps as or,
+        _mm256_set1_ps as set1, _mm256_sqrt_ps as sqrt, _mm256_sub_ps as sub, _mm256_xor_ps as xor,
+    };
+}
+use self::reg::Reg;
+
+// SAFETY: the intrinsics below need AVX, which this module is only compiled
+// with (see `simd.rs`)
+
+#[derive(Clone, Copy)]
+pub struct Floats(Reg);
+
+// All bits of a lane are set where the comparison held
+#[derive(Clone, Copy)]
+pub struct Mask(Reg);
+
+impl Floats {
+    #[inline(always)]
+    pub fn splat(v: Num) -> Floats {
+        Floats(unsafe { reg::set1(v) })
+    }
+    #[inline(always)]
+    pub fn from_array(a: [Num; LANES]) -> Floats {
+        // SAFETY: both are 32 bytes of plain floats
+        Floats(unsafe { mem::transmute::<[Num; LANES], Reg>(a) })
+    }
+    #[inline(always)]
+    pub fn to_array(self) -> [Num; LANES] {
+        // SAFETY: both are 32 bytes of plain floats
+        unsafe { mem::transmute::<Reg, [Num; LANES]>(self.0) }
+    }
+
+    #[inline(always)]
+    pub fn min(self, o: Floats) -> Floats {
+        Floats(unsafe { reg::min(self.0, o.0) })
+    }
+    #[inline(always)]
+    pub fn max(self, o: Floats) -> Floats {
+        Floats(unsafe { reg::max(self.0, o.0) })
+    }
+    #[inline(always)]
+    pub fn sqrt(self) -> Floats {
+        Floats(unsafe { reg::sqrt(self.0) })
+    }
+
+    #[inline(always)]
+    pub fn lt(self, o: Floats) -> Mask {
+        Mask(unsafe { reg::cmp::<_CMP_LT_OQ>(self.0, o.0) })
+    }
+    #[inline(always)]
+    pub fn le(self, o: Floats) -> Mask {
+        Mask(unsafe { reg::cmp::<_CMP_LE_OQ>(self.0, o.0) })
+    }
+    #[inline(always)]
+    pub fn gt(self, o: Floats) -> Mask {
+        Mask(unsafe { reg::cmp::<_CMP_GT_OQ>(self.0, o.0) })
+    }
+    #[inline(always)]
+    pub fn ge(self, o: Floats) -> Mask {
+        Mask(unsafe { reg::cmp::<_CMP_GE_OQ>(self.0, o.0) })
+    }
+}
+
+impl Mask {
+    #[inline(always)]
+    pub fn splat(v: bool) -> Mask {
+        let zero = unsafe { reg::set1(0.) };
+        if v {
+            Mask(unsafe { reg::cmp::<_CMP_TRUE_UQ>(zero, zero) })
+        } else {
+            Mask(zero)
+        }
+    }
+    // Lanes of `a` where the mask is set, of `b` elsewhere
+    #[inline(always)]
+    pub fn select(self, a: Floats, b: Floats) -> Floats {
+        Floats(unsafe { reg::blendv(b.0, a.0, self.0) })
+    }
+    // Bit `i` is set for lane `i`
+    #[inline(always)]
+    pub fn bits(self) -> u32 {
+        unsafe { reg::movemask(self.0) as u32 }
+    }
+    #[inline(always)]
+    pub fn any(self) -> bool {
+        self.bits() != 0
+    }
+}
+
+impl ops::Add for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn add(self, o: Floats) -> Floats {
+        Floats(unsafe { reg::add(self.0, o.0) })
+    }
+}
+impl ops::Sub for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn sub(self, o: Floats) -> Floats {
+        Floats(unsafe { reg::sub(self.0, o.0) })
+    }
+}
+impl ops::Mul for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn mul(self, o: Floats) -> Floats {
+        Floats(unsafe { reg::mul(self.0, o.0) })
+    }
+}
+impl ops::Div for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn div(self, o: Floats) -> Floats {
+        Floats(unsafe { reg::div(self.0, o.0) })
+    }
+}
+impl ops::Neg for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn neg(self) -> Floats {
+        Floats(unsafe { reg::xor(self.0, reg::set1(-0.)) })
+    }
+}
+
+impl ops::BitAnd for Mask {
+    type Output = Mask;
+    #[inline(always)]
+    fn bitand(self, o: Mask) -> Mask {
+        Mask(unsafe { reg::and(self.0, o.0) })
+    }
+}
+impl ops::BitOr for Mask {
+    type Output = Mask;
+    #[inline(always)]
+    fn bitor(self, o: Mask) -> Mask {
+        Mask(unsafe { reg::or(self.0, o.0) })
+    }
+}
+impl ops::Not for Mask {
+    type Output = Mask;
+    #[inline(always)]
+    fn not(self) -> Mask {
+        Mask(unsafe { reg::xor(self.0, Mask::splat(true).0) })
+    }
+}
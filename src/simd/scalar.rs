@@ -0,0 +1,154 @@
+use std::ops;
+
+use super::LANES;
+use crate::Num;
+
+// Portable fallback, written lane by lane so the compiler can still
+// vectorize it for whatever the target supports
+#[derive(Clone, Copy)]
+pub struct Floats([Num; LANES]);
+
+#[derive(Clone, Copy)]
+pub struct Mask([bool; LANES]);
+
+impl Floats {
+    #[inline(always)]
+    pub fn splat(v: Num) -> Floats {
+        Floats([v; LANES])
+    }
+    #[inline(always)]
+    pub fn from_array(a: [Num; LANES]) -> Floats {
+        Floats(a)
+    }
+    #[inline(always)]
+    pub fn to_array(self) -> [Num; LANES] {
+        self.0
+    }
+
+    #[inline(always)]
+    fn map(self, f: impl Fn(Num) -> Num) -> Floats {
+        Floats(self.0.map(f))
+    }
+    #[inline(always)]
+    fn zip(self, o: Floats, f: impl Fn(Num, Num) -> Num) -> Floats {
+        Floats(std::array::from_fn(|i| f(self.0[i], o.0[i])))
+    }
+    #[inline(always)]
+    fn compare(self, o: Floats, f: impl Fn(Num, Num) -> bool) -> Mask {
+        Mask(std::array::from_fn(|i| f(self.0[i], o.0[i])))
+    }
+
+    #[inline(always)]
+    pub fn min(self, o: Floats) -> Floats {
+        self.zip(o, Num::min)
+    }
+    #[inline(always)]
+    pub fn max(self, o: Floats) -> Floats {
+        self.zip(o, Num::max)
+    }
+    #[inline(always)]
+    pub fn sqrt(self) -> Floats {
+        self.map(Num::sqrt)
+    }
+
+    #[inline(always)]
+    pub fn lt(self, o: Floats) -> Mask {
+        self.compare(o, |a, b| a < b)
+    }
+    #[inline(always)]
+    pub fn le(self, o: Floats) -> Mask {
+        self.compare(o, |a, b| a <= b)
+    }
+    #[inline(always)]
+    pub fn gt(self, o: Floats) -> Mask {
+        self.compare(o, |a, b| a > b)
+    }
+    #[inline(always)]
+    pub fn ge(self, o: Floats) -> Mask {
+        self.compare(o, |a, b| a >= b)
+    }
+}
+
+impl Mask {
+    #[inline(always)]
+    pub fn splat(v: bool) -> Mask {
+        Mask([v; LANES])
+    }
+    // Lanes of `a` where the mask is set, of `b` elsewhere
+    #[inline(always)]
+    pub fn select(self, a: Floats, b: Floats) -> Floats {
+        Floats(std::array::from_fn(
+            |i| if self.0[i] { a.0[i] } else { b.0[i] },
+        ))
+    }
+    // Bit `i` is set for lane `i`
+    #[inline(always)]
+    pub fn bits(self) -> u32 {
+        self.0
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, &m)| bits | ((m as u32) << i))
+    }
+    #[inline(always)]
+    pub fn any(self) -> bool {
+        self.0.iter().any(|&m| m)
+    }
+}
+
+impl ops::Add for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn add(self, o: Floats) -> Floats {
+        self.zip(o, |a, b| a + b)
+    }
+}
+impl ops::Sub for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn sub(self, o: Floats) -> Floats {
+        self.zip(o, |a, b| a - b)
+    }
+}
+impl ops::Mul for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn mul(self, o: Floats) -> Floats {
+        self.zip(o, |a, b| a * b)
+    }
+}
+impl ops::Div for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn div(self, o: Floats) -> Floats {
+        self.zip(o, |a, b| a / b)
+    }
+}
+impl ops::Neg for Floats {
+    type Output = Floats;
+    #[inline(always)]
+    fn neg(self) -> Floats {
+        self.map(|a| -a)
+    }
+}
+
+impl ops::BitAnd for Mask {
+    type Output = Mask;
+    #[inline(always)]
+    fn bitand(self, o: Mask) -> Mask {
+        Mask(std::array::from_fn(|i| self.0[i] & o.0[i]))
+    }
+}
+impl ops::BitOr for Mask {
+    type Output = Mask;
+    #[inline(always)]
+    fn bitor(self, o: Mask) -> Mask {
+        Mask(std::array::from_fn(|i| self.0[i] | o.0[i]))
+    }
+}
+impl ops::Not for Mask {
+    type Output = Mask;
+    #[inline(always)]
+    fn not(self) -> Mask {
+        Mask(self.0.map(|m| !m))
+    }
+}
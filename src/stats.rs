@@ -0,0 +1,210 @@
+use std::{
+    cell::Cell,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+// Snapshot of a running render passed to the ProgressObserver
+pub struct Progress {
+    pub fraction: f64,         // Completed part of the render, 0 to 1
+    pub elapsed: Duration,     // Time since rendering started
+    pub eta: Option<Duration>, // Estimated time until the render finishes
+    pub samples_per_sec: f64,  // Camera samples per second so far
+}
+
+// Counters and timings of a finished render
+#[derive(Clone, Default, Debug)]
+pub struct RenderStats {
+    pub samples: u64,            // Camera paths traced
+    pub rays_cast: u64,          // Rays traced against the scene, of all path segments
+    pub intersection_tests: u64, // Ray-object intersection tests
+
+    pub total_time: Duration,
+    pub render_time: Duration,     // Time spent tracing paths
+    pub snapshot_time: Duration,   // Time spent in progressive snapshot callbacks
+    pub checkpoint_time: Duration, // Time spent writing checkpoints
+}
+
+impl RenderStats {
+    // Average number of path segments per camera sample
+    pub fn average_path_depth(&self) -> f64 {
+        self.rays_cast as f64 / f64::max(self.samples as f64, 1.)
+    }
+
+    pub fn samples_per_sec(&self) -> f64 {
+        self.samples as f64 / f64::max(self.render_time.as_secs_f64(), 1e-9)
+    }
+}
+
+impl Display for RenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "samples:            {}", self.samples)?;
+        writeln!(f, "rays cast:          {}", self.rays_cast)?;
+        writeln!(f, "intersection tests: {}", self.intersection_tests)?;
+        writeln!(f, "average path depth: {:.2}", self.average_path_depth())?;
+        writeln!(f, "samples/sec:        {:.0}", self.samples_per_sec())?;
+        writeln!(f, "render time:        {:.2?}", self.render_time)?;
+        writeln!(f, "snapshot time:      {:.2?}", self.snapshot_time)?;
+        writeln!(f, "checkpoint time:    {:.2?}", self.checkpoint_time)?;
+        write!(f, "total time:         {:.2?}", self.total_time)
+    }
+}
+
+// Receives progress reports while the camera renders.
+// Called from the worker threads, at most once every `progress_interval`.
+pub trait ProgressObserver: Send + Sync {
+    fn on_progress(&self, progress: &Progress);
+    fn on_finish(&self, _stats: &RenderStats) {}
+}
+
+impl<F: Fn(&Progress) + Send + Sync> ProgressObserver for F {
+    fn on_progress(&self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+// Per thread counters, so tracing rays does not touch shared memory
+#[derive(Clone, Copy, Default)]
+struct Counters {
+    samples: u64,
+    rays_cast: u64,
+    intersection_tests: u64,
+}
+
+thread_local! {
+    static COUNTERS: Cell<Counters> = Cell::new(Counters::default());
+}
+
+pub(crate) fn record_ray(intersection_tests: usize) {
+    COUNTERS.with(|c| {
+        let mut counters = c.get();
+        counters.rays_cast += 1;
+        counters.intersection_tests += intersection_tests as u64;
+        c.set(counters);
+    });
+}
+
+pub(crate) fn record_sample() {
+    COUNTERS.with(|c| {
+        let mut counters = c.get();
+        counters.samples += 1;
+        c.set(counters);
+    });
+}
+
+// Collects the counters of all worker threads and reports progress
+pub(crate) struct RenderTracker {
+    observer: Option<Arc<dyn ProgressObserver>>,
+    interval: Duration,
+    start: Instant,
+    last_report: Mutex<Instant>,
+
+    samples: AtomicU64,
+    rays_cast: AtomicU64,
+    intersection_tests: AtomicU64,
+
+    // Fractions of the whole render at the start and end of the current
+    // pass, and the rows of the pass done so far
+    pass_range: Mutex<(f64, f64)>,
+    rows_done: AtomicU64,
+    rows: AtomicU64,
+
+    pub stats: Mutex<RenderStats>,
+}
+
+impl RenderTracker {
+    pub fn new(observer: Option<Arc<dyn ProgressObserver>>, interval: Duration) -> RenderTracker {
+        let start = Instant::now();
+        RenderTracker {
+            observer,
+            interval,
+            start,
+            last_report: Mutex::new(start),
+            samples: AtomicU64::new(0),
+            rays_cast: AtomicU64::new(0),
+            intersection_tests: AtomicU64::new(0),
+            pass_range: Mutex::new((0., 1.)),
+            rows_done: AtomicU64::new(0),
+            rows: AtomicU64::new(1),
+            stats: Mutex::new(RenderStats::default()),
+        }
+    }
+
+    pub fn start_pass(&self, from: f64, to: f64, rows: i32) {
+        *self.pass_range.lock().unwrap() = (from, to);
+        self.rows_done.store(0, Ordering::Relaxed);
+        self.rows.store(rows.max(1) as u64, Ordering::Relaxed);
+    }
+
+    // Called by a worker after each row, moves its thread counters over
+    pub fn row_done(&self) {
+        let counters = COUNTERS.with(|c| c.replace(Counters::default()));
+        self.samples.fetch_add(counters.samples, Ordering::Relaxed);
+        self.rays_cast
+            .fetch_add(counters.rays_cast, Ordering::Relaxed);
+        self.intersection_tests
+            .fetch_add(counters.intersection_tests, Ordering::Relaxed);
+        let rows_done = self.rows_done.fetch_add(1, Ordering::Relaxed) + 1;
+
+        let Some(observer) = &self.observer else {
+            return;
+        };
+        {
+            let mut last_report = self.last_report.lock().unwrap();
+            if last_report.elapsed() < self.interval {
+                return;
+            }
+            *last_report = Instant::now();
+        }
+
+        let (from, to) = *self.pass_range.lock().unwrap();
+        let pass_fraction = rows_done as f64 / self.rows.load(Ordering::Relaxed) as f64;
+        let fraction = from + (to - from) * pass_fraction;
+        observer.on_progress(&self.progress(fraction));
+    }
+
+    fn progress(&self, fraction: f64) -> Progress {
+        let elapsed = self.start.elapsed();
+        let eta = if fraction > 0. {
+            Some(elapsed.mul_f64((1. - fraction) / fraction))
+        } else {
+            None
+        };
+        Progress {
+            fraction,
+            elapsed,
+            eta,
+            samples_per_sec: self.samples.load(Ordering::Relaxed) as f64
+                / f64::max(elapsed.as_secs_f64(), 1e-9),
+        }
+    }
+
+    // Time a phase of the render into one of the RenderStats durations
+    pub fn time<T>(
+        &self,
+        phase: fn(&mut RenderStats) -> &mut Duration,
+        f: impl FnOnce() -> T,
+    ) -> T {
+        let start = Instant::now();
+        let result = f();
+        *phase(&mut self.stats.lock().unwrap()) += start.elapsed();
+        result
+    }
+
+    pub fn finish(&self) -> RenderStats {
+        let mut stats = self.stats.lock().unwrap().clone();
+        stats.samples = self.samples.load(Ordering::Relaxed);
+        stats.rays_cast = self.rays_cast.load(Ordering::Relaxed);
+        stats.intersection_tests = self.intersection_tests.load(Ordering::Relaxed);
+        stats.total_time = self.start.elapsed();
+
+        if let Some(observer) = &self.observer {
+            observer.on_finish(&stats);
+        }
+        stats
+    }
+}
@@ -1,6 +1,6 @@
 use rand::Rng;
 
-use crate::Num;
+use crate::{consts, Num};
 use std::{fmt::Display, ops};
 
 #[derive(Copy, Clone)]
@@ -34,7 +34,7 @@ impl Vec3 {
     pub fn random_unit_sphere() -> Vec3 {
         let mut rand = rand::thread_rng();
         // ON unit sphere
-        let theta = rand.gen_range((0.)..std::f64::consts::PI * 2.);
+        let theta = rand.gen_range((0.)..consts::PI * 2.);
         let phi = (rand.gen_range((-1.)..1.) as Num).acos();
         Vec3 {
             x: phi.sin() * theta.cos(),
@@ -63,7 +63,7 @@ impl Vec3 {
     }
     pub fn random_unit_in_disk() -> Vec3 {
         let mut rand = rand::thread_rng();
-        // let theta = rand.gen::<Num>() * std::f64::consts::PI * 2.;
+        // let theta = rand.gen::<Num>() * consts::PI * 2.;
         // Vec3::new(theta.cos(), theta.sin(), 0.) * rand.gen::<Num>()
         loop {
             let p = Vec3::new(rand.gen_range((-1.)..1.), rand.gen_range((-1.)..1.), 0.);
@@ -72,6 +72,21 @@ impl Vec3 {
             }
         }
     }
+    // Point on the unit sphere from two uniform samples in [0,1)
+    pub fn sample_unit_sphere(u: (Num, Num)) -> Vec3 {
+        let theta = u.0 * consts::PI * 2.;
+        let phi = (1. - 2. * u.1).acos();
+        Vec3 {
+            x: phi.sin() * theta.cos(),
+            y: phi.sin() * theta.sin(),
+            z: phi.cos(),
+        }
+    }
+    // Point inside the unit disk from two uniform samples in [0,1)
+    pub fn sample_unit_in_disk(u: (Num, Num)) -> Vec3 {
+        let theta = u.0 * consts::PI * 2.;
+        Vec3::new(theta.cos(), theta.sin(), 0.) * u.1.sqrt()
+    }
 
     pub fn lenght_sqr(&self) -> Num {
         self.x * self.x + self.y * self.y + self.z * self.z
@@ -229,3 +244,33 @@ pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: Num) -> Vec3 {
 }
 
 pub type Point3 = Vec3;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // loose enough for the `f32` feature
+    const EPS: Num = 1e-5;
+
+    #[test]
+    fn sampled_points_are_on_the_sphere_and_in_the_disk() {
+        for k in 0..32 {
+            let u = (k as Num / 32., ((k * 13) % 32) as Num / 32.);
+            assert!((Vec3::sample_unit_sphere(u).lenght() - 1.).abs() < EPS);
+            assert!(Vec3::sample_unit_in_disk(u).lenght() <= 1. + EPS);
+        }
+    }
+
+    #[test]
+    fn refraction_follows_snells_law() {
+        let n = Vec3::new(0., 1., 0.);
+        let uv = normalize(&Vec3::new(1., -1., 0.));
+        let refracted = refract(&uv, &n, 1. / 1.5);
+        let sin_in = cross(&uv, &n).lenght();
+        let sin_out = cross(&normalize(&refracted), &n).lenght();
+        assert!((sin_in - 1.5 * sin_out).abs() < EPS);
+
+        let reflected = reflect(&uv, &n);
+        assert!((reflected.x - uv.x).abs() < EPS && (reflected.y + uv.y).abs() < EPS);
+    }
+}